@@ -39,7 +39,8 @@ struct FragmentInput {
     @location(2) neighbors: u32,
 };
 
-/// Entry point for the fragment shader
+/// Entry point for the fragment shader.
+/// The shape of the tiles must match `TileShape` on the CPU side, which is used for collisions.
 @fragment
 fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
     var color = in.color;
@@ -0,0 +1,256 @@
+use std::{fmt, io, path::PathBuf};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::world::map_format::{self, MAX_SIDE_LENGTH, MAX_TILE_COUNT};
+
+/// The config file that is used if it exists and no other config file is given
+const DEFAULT_CONFIG_PATH: &str = "tanks.ron";
+/// The allowed values of [`GameConfig::physics_rate`]
+const PHYSICS_RATE_RANGE: std::ops::RangeInclusive<f64> = 1.0..=10_000.0;
+
+pub const USAGE: &str = "\
+Usage: tanks [OPTIONS]
+
+Options:
+    --config <PATH>     Read the game config from this RON file (default: tanks.ron, if it exists)
+    --map <PATH>        Play on a map file (.map) or image instead of a generated world
+    --seed <SEED>       The seed of the generated world
+    --width <TILES>     The width of the generated world
+    --height <TILES>    The height of the generated world
+    --tile-size <PX>    The size of a tile on the screen in pixels
+    --physics-rate <HZ> How many physics steps run per second
+    --wind <TILES/S²>   The horizontal acceleration of shells by the wind, positive to the right
+    --help              Print this message";
+
+/// The settings of a game, read from the config file and the command line arguments
+#[derive(Debug, Clone, Resource, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameConfig {
+    /// A map file or image to play on instead of a generated world
+    pub map: Option<PathBuf>,
+    /// The seed of the generated world, random if not set
+    pub seed: Option<u64>,
+    /// The width of the generated world in tiles
+    pub width: usize,
+    /// The height of the generated world in tiles
+    pub height: usize,
+    /// The size of a tile on the screen in pixels
+    pub tile_size: f32,
+    /// How many physics steps run per second, independent of the frame rate
+    pub physics_rate: f64,
+    /// The horizontal acceleration of shells by the wind in tiles per second², positive to the right
+    pub wind: f32,
+    /// The color of the sky as rgb values between 0 and 1
+    pub clear_color: [f32; 3],
+    /// The position of the tank in tiles.
+    /// If not set, the tank spawns on the surface close to the left edge of the world.
+    pub tank_spawn: Option<[f32; 2]>,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            map: None,
+            seed: None,
+            width: 200,
+            height: 100,
+            tile_size: 8.0,
+            physics_rate: 64.0,
+            wind: 0.0,
+            clear_color: [0.5, 0.8, 0.99],
+            tank_spawn: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The `--help` flag was passed
+    HelpRequested,
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, ron::error::SpannedError),
+    InvalidArgument(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::HelpRequested => write!(f, "{USAGE}"),
+            ConfigError::Io(path, error) => {
+                write!(f, "Could not read config {}: {error}", path.display())
+            }
+            ConfigError::Parse(path, error) => {
+                write!(f, "Could not parse config {}: {error}", path.display())
+            }
+            ConfigError::InvalidArgument(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl GameConfig {
+    /// Reads the config file and overrides its values with the given command line arguments
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, ConfigError> {
+        let mut options = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--help" {
+                return Err(ConfigError::HelpRequested);
+            }
+            if !arg.starts_with("--") {
+                return Err(ConfigError::InvalidArgument(format!(
+                    "Unexpected argument '{arg}'"
+                )));
+            }
+            let value = args.next().ok_or_else(|| {
+                ConfigError::InvalidArgument(format!("Missing value for '{arg}'"))
+            })?;
+            options.push((arg, value));
+        }
+
+        let config_path = options
+            .iter()
+            .find(|(option, _)| option == "--config")
+            .map(|(_, path)| PathBuf::from(path));
+        let mut config = match config_path {
+            Some(path) => Self::read(path)?,
+            None if std::path::Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Self::read(DEFAULT_CONFIG_PATH.into())?
+            }
+            None => Self::default(),
+        };
+
+        for (option, value) in options {
+            match option.as_str() {
+                "--config" => {}
+                "--map" => config.map = Some(value.into()),
+                "--seed" => config.seed = Some(parse_value(&option, &value)?),
+                "--width" => config.width = parse_value(&option, &value)?,
+                "--height" => config.height = parse_value(&option, &value)?,
+                "--tile-size" => config.tile_size = parse_value(&option, &value)?,
+                "--physics-rate" => config.physics_rate = parse_value(&option, &value)?,
+                "--wind" => config.wind = parse_value(&option, &value)?,
+                _ => {
+                    return Err(ConfigError::InvalidArgument(format!(
+                        "Unknown option '{option}'"
+                    )))
+                }
+            }
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks that all values can be used to start a game
+    fn validate(&self) -> Result<(), ConfigError> {
+        // Larger worlds couldn't be saved as maps
+        if map_format::check_size(self.width, self.height).is_err() {
+            return Err(ConfigError::InvalidArgument(format!(
+                "The world size must be between 1x1 and {MAX_SIDE_LENGTH}x{MAX_SIDE_LENGTH} tiles \
+                 with at most {MAX_TILE_COUNT} tiles in total, got {}x{}",
+                self.width, self.height
+            )));
+        }
+        if !(self.tile_size.is_finite() && self.tile_size > 0.0) {
+            return Err(ConfigError::InvalidArgument(format!(
+                "The tile size must be a positive number, got {}",
+                self.tile_size
+            )));
+        }
+        // Keeps the simulation from being too coarse to be stable or too fine to keep up in real time
+        if !PHYSICS_RATE_RANGE.contains(&self.physics_rate) {
+            return Err(ConfigError::InvalidArgument(format!(
+                "The physics rate must be between {} and {} steps per second, got {}",
+                PHYSICS_RATE_RANGE.start(),
+                PHYSICS_RATE_RANGE.end(),
+                self.physics_rate
+            )));
+        }
+        Ok(())
+    }
+
+    fn read(path: PathBuf) -> Result<Self, ConfigError> {
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(error) => return Err(ConfigError::Io(path, error)),
+        };
+        ron::from_str(&content).map_err(|error| ConfigError::Parse(path, error))
+    }
+
+    pub fn clear_color(&self) -> Color {
+        let [r, g, b] = self.clear_color;
+        Color::rgb(r, g, b)
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, ConfigError> {
+    value.parse().map_err(|_| {
+        ConfigError::InvalidArgument(format!("Invalid value '{value}' for '{option}'"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a config file into the temporary directory and returns its path
+    fn write_config(name: &str, content: &str) -> String {
+        let path = std::env::temp_dir().join(format!("tanks-test-{name}.ron"));
+        std::fs::write(&path, content).unwrap();
+        path.to_str().unwrap().to_owned()
+    }
+
+    fn from_args(args: &[&str]) -> Result<GameConfig, ConfigError> {
+        GameConfig::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn invalid_arguments_are_errors() {
+        let config = write_config("empty", "()");
+        for args in [
+            ["--config", &config, "--size", "10"].as_slice(),
+            &["--config", &config, "--width"],
+            &["--config", &config, "--width", "wide"],
+            &["--config", &config, "--seed", "-1"],
+            &["--config", &config, "10"],
+        ] {
+            assert!(
+                matches!(from_args(args), Err(ConfigError::InvalidArgument(_))),
+                "{args:?}"
+            );
+        }
+        assert!(matches!(
+            from_args(&["--help"]),
+            Err(ConfigError::HelpRequested)
+        ));
+    }
+
+    #[test]
+    fn arguments_override_the_config_file() {
+        let path = write_config("override", "(width: 300, height: 150, wind: 2.5)");
+        let config = from_args(&["--config", &path, "--width", "120"]).unwrap();
+        assert_eq!(config.width, 120);
+        assert_eq!(config.height, 150);
+        assert_eq!(config.wind, 2.5);
+        // Values that are in neither keep their default
+        assert_eq!(config.physics_rate, GameConfig::default().physics_rate);
+    }
+
+    #[test]
+    fn unknown_fields_in_the_config_file_are_errors() {
+        let path = write_config("typo", "(widht: 300)");
+        assert!(matches!(
+            from_args(&["--config", &path]),
+            Err(ConfigError::Parse(..))
+        ));
+        let missing = std::env::temp_dir().join("tanks-test-missing.ron");
+        assert!(matches!(
+            from_args(&["--config", missing.to_str().unwrap()]),
+            Err(ConfigError::Io(..))
+        ));
+    }
+}
@@ -0,0 +1,294 @@
+use bevy::{input::InputSystem, prelude::*, window::PrimaryWindow};
+
+use crate::{
+    physics::{Grounded, Rigidbody},
+    TILE_SIZE,
+};
+
+/// Turns the keyboard and mouse input into a [`MoveCommand`], [`AimCommand`] and [`FireCommand`]
+/// for every physics step.
+///
+/// Input is only sampled once per frame, but the physics can run any number of steps per frame.
+/// Collecting it into per-step commands makes the physics only depend on the commands,
+/// so the same commands always lead to the same result, no matter the frame rate.
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SampledInput>()
+            .add_systems(PreUpdate, (sample_input, sample_cursor).after(InputSystem))
+            .add_systems(FixedPreUpdate, issue_commands)
+            .add_systems(FixedUpdate, apply_move_commands);
+    }
+}
+
+/// The acceleration of bodies that are moved by commands, in tiles per second²
+const MOVE_ACCELERATION: f32 = 60.0;
+/// Bodies are only accelerated by commands until they reach this speed in tiles per second
+const MAX_MOVE_SPEED: f32 = 10.0;
+
+/// The impulse that bodies on the ground get when jumping
+const JUMP_IMPULSE: f32 = 15.0;
+
+/// Entities with this component get their commands from the keyboard
+#[derive(Component, Debug)]
+pub struct Player;
+
+/// What a body should do during the current physics step
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq)]
+pub struct MoveCommand {
+    /// The direction to drive in, between -1 and 1 on the x axis.
+    /// The y axis is ignored, bodies can only gain height by jumping.
+    pub direction: Vec2,
+    /// Whether the body should jump if it is on the ground
+    pub jump: bool,
+}
+
+/// How a tank aims its turret during the current physics step
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq)]
+pub struct AimCommand {
+    /// The direction to turn the turret in, between -1 and 1, positive is counterclockwise
+    pub turn: f32,
+    /// The direction to change the power in, between -1 and 1
+    pub power: f32,
+    /// A point in tiles that the turret aims at directly instead of turning.
+    /// The further away it is, the higher the power.
+    pub target: Option<Vec2>,
+}
+
+/// Whether a tank fires its turret during the current physics step
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq)]
+pub struct FireCommand(pub bool);
+
+/// The input since the last physics step
+#[derive(Resource, Debug, Default)]
+struct SampledInput {
+    direction: Vec2,
+    /// Stays set until a physics step used it, so short presses between two steps aren't lost
+    jump: bool,
+    /// Stays set until a physics step used it, like `jump`
+    fire: bool,
+    aim: AimCommand,
+}
+
+fn sample_input(input: Res<ButtonInput<KeyCode>>, mut sampled: ResMut<SampledInput>) {
+    let mut direction = 0.0;
+    if input.pressed(KeyCode::ArrowRight) {
+        direction += 1.0;
+    }
+    if input.pressed(KeyCode::ArrowLeft) {
+        direction -= 1.0
+    }
+    sampled.direction = Vec2::X * direction;
+    sampled.jump |= input.just_pressed(KeyCode::Space);
+    sampled.fire |= input.just_pressed(KeyCode::Enter);
+
+    // A and D turn the turret, W and S change the power
+    let mut turn = 0.0;
+    if input.pressed(KeyCode::KeyA) {
+        turn += 1.0;
+    }
+    if input.pressed(KeyCode::KeyD) {
+        turn -= 1.0;
+    }
+    let mut power = 0.0;
+    if input.pressed(KeyCode::KeyW) {
+        power += 1.0;
+    }
+    if input.pressed(KeyCode::KeyS) {
+        power -= 1.0;
+    }
+    sampled.aim.turn = turn;
+    sampled.aim.power = power;
+}
+
+/// The turret aims at the cursor while the middle mouse button is held
+fn sample_cursor(
+    buttons: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    mut sampled: ResMut<SampledInput>,
+) {
+    sampled.aim.target = None;
+    if !buttons.pressed(MouseButton::Middle) {
+        return;
+    }
+    let (camera, camera_transform) = cameras.single();
+    sampled.aim.target = windows
+        .single()
+        .cursor_position()
+        .and_then(|position| camera.viewport_to_world_2d(camera_transform, position))
+        .map(|cursor| cursor / TILE_SIZE);
+}
+
+/// This system gives the sampled input to the players as the command for the next physics step
+#[allow(clippy::type_complexity)]
+fn issue_commands(
+    mut sampled: ResMut<SampledInput>,
+    mut players: Query<
+        (
+            &mut MoveCommand,
+            Option<&mut FireCommand>,
+            Option<&mut AimCommand>,
+        ),
+        With<Player>,
+    >,
+) {
+    let command = MoveCommand {
+        direction: sampled.direction,
+        jump: std::mem::take(&mut sampled.jump),
+    };
+    let fire = std::mem::take(&mut sampled.fire);
+    for (mut move_command, fire_command, aim_command) in players.iter_mut() {
+        *move_command = command;
+        if let Some(mut fire_command) = fire_command {
+            fire_command.0 = fire;
+        }
+        if let Some(mut aim_command) = aim_command {
+            *aim_command = sampled.aim;
+        }
+    }
+}
+
+pub(crate) fn apply_move_commands(
+    mut bodies: Query<(&mut Rigidbody, &MoveCommand, Option<&Grounded>)>,
+) {
+    for (mut body, command, grounded) in bodies.iter_mut() {
+        if command.jump && grounded.is_some_and(|grounded| grounded.0) {
+            body.apply_impulse(Vec2::Y * JUMP_IMPULSE);
+        }
+        // Driving only accelerates sideways, otherwise bodies could fly against gravity.
+        // Don't accelerate any further once the maximum speed is reached.
+        let direction = command.direction.x.clamp(-1.0, 1.0);
+        body.acceleration = if body.velocity.x * direction < MAX_MOVE_SPEED {
+            Vec2::X * direction * MOVE_ACCELERATION
+        } else {
+            Vec2::ZERO
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::{ecs::system::RunSystemOnce, time::TimeUpdateStrategy};
+
+    use super::*;
+    use crate::{
+        physics::PhysicsPlugin,
+        tank::TankBundle,
+        world::{World, WorldTile},
+        GameWorld, TILE_SIZE,
+    };
+
+    /// How many physics steps each simulation runs
+    const TICKS: u32 = 192;
+
+    /// The number of physics steps that have run so far
+    #[derive(Resource, Default)]
+    struct TicksRun(u32);
+
+    /// The commands of the first tank: drive right, jump, then drive back to the left
+    fn scripted_command(tick: u32) -> MoveCommand {
+        MoveCommand {
+            direction: if tick < 120 { Vec2::X } else { Vec2::NEG_X },
+            jump: tick == 60,
+        }
+    }
+
+    /// Replaces the keyboard input of the player with the script
+    fn run_script(
+        mut ticks: ResMut<TicksRun>,
+        mut commands: Query<&mut MoveCommand, With<Player>>,
+    ) {
+        for mut command in commands.iter_mut() {
+            *command = scripted_command(ticks.0);
+        }
+        ticks.0 += 1;
+    }
+
+    /// A flat ground with a ramp and a wall
+    fn test_world() -> World {
+        World::filled(100, 60, |x, y| {
+            let height = 10 + (x - 40).clamp(0, 8) + if x >= 70 { 20 } else { 0 };
+            if y < height {
+                WorldTile::Dirt
+            } else {
+                WorldTile::Air
+            }
+        })
+    }
+
+    /// Runs the scripted commands with the given time between frames
+    /// and returns the final position and velocity of every tank as bits
+    fn simulate(frame_time: Duration) -> Vec<[u32; 4]> {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<Shader>()
+            .add_plugins((
+                bevy::input::InputPlugin,
+                bevy::gizmos::GizmoPlugin,
+                PhysicsPlugin { tick_rate: 64.0 },
+                ControlsPlugin,
+            ))
+            .insert_resource(GameWorld(test_world()))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(frame_time))
+            .init_resource::<TicksRun>()
+            .add_systems(FixedPreUpdate, run_script.after(issue_commands));
+
+        let tanks: Vec<Entity> = [20.0, 35.0]
+            .map(|x| {
+                let position = Vec3::new(x, 16.0, 0.0) * TILE_SIZE;
+                let tank = app.world.spawn(TankBundle {
+                    spatial_bundle: SpatialBundle::from_transform(Transform::from_translation(
+                        position,
+                    )),
+                    ..default()
+                });
+                tank.id()
+            })
+            .into();
+        // Only the first tank follows the script, the other one gets pushed around
+        app.world.entity_mut(tanks[0]).insert(Player);
+
+        while app.world.resource::<TicksRun>().0 < TICKS {
+            app.update();
+        }
+        assert_eq!(app.world.resource::<TicksRun>().0, TICKS);
+
+        tanks
+            .iter()
+            .map(|tank| {
+                let translation = app.world.get::<Transform>(*tank).unwrap().translation;
+                let velocity = app.world.get::<Rigidbody>(*tank).unwrap().velocity;
+                [translation.x, translation.y, velocity.x, velocity.y].map(f32::to_bits)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn physics_is_independent_of_frame_rate() {
+        let at_tick_rate = simulate(Duration::from_secs_f64(1.0 / 64.0));
+        assert_eq!(at_tick_rate, simulate(Duration::from_secs_f64(1.0 / 64.0)));
+        assert_eq!(at_tick_rate, simulate(Duration::from_secs_f64(1.0 / 32.0)));
+        assert_eq!(at_tick_rate, simulate(Duration::from_secs_f64(1.0 / 128.0)));
+    }
+
+    #[test]
+    fn commands_only_drive_sideways() {
+        let mut world = bevy::ecs::world::World::new();
+        let body = world
+            .spawn((
+                Rigidbody::default(),
+                MoveCommand {
+                    direction: Vec2::new(-1.0, 1.0),
+                    jump: false,
+                },
+            ))
+            .id();
+        world.run_system_once(apply_move_commands);
+        let acceleration = world.get::<Rigidbody>(body).unwrap().acceleration;
+        assert_eq!(acceleration, Vec2::NEG_X * MOVE_ACCELERATION);
+    }
+}
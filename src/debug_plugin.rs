@@ -1,8 +1,14 @@
 use bevy::{input::mouse::MouseWheel, prelude::*, window::PrimaryWindow};
 
 use crate::{
-    physics::{Collider, Rigidbody},
-    world::{World, WorldTile},
+    controls::Player,
+    physics::{
+        body_collision::BodyCollision,
+        collider::{Collider, ColliderShape},
+        Intersection, Rigidbody, WorldTransform,
+    },
+    projectile::Explosion,
+    world::{image_map::ImagePalette, World, WorldTile},
     GameWorld, TILE_SIZE,
 };
 
@@ -10,41 +16,26 @@ pub struct DebugPlugin;
 
 impl Plugin for DebugPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup)
-            .add_systems(Update, (move_box, input, show_cursor_selection))
-            .add_systems(FixedUpdate, let_box_fall);
+        app.add_systems(Startup, setup).add_systems(
+            Update,
+            (
+                input,
+                spawn_bodies,
+                show_cursor_selection,
+                show_line_of_sight,
+                show_colliders,
+                show_contacts,
+                log_body_collisions,
+                log_explosions,
+                save_world,
+                export_world_image,
+            ),
+        );
     }
 }
 
 fn setup() {}
 
-fn let_box_fall(time: Res<Time>, mut colliders: Query<&mut Transform, With<Collider>>) {
-    for mut transform in colliders.iter_mut() {
-        transform.translation.y -= time.delta_seconds() * 4.0 * TILE_SIZE;
-    }
-}
-
-fn move_box(input: Res<ButtonInput<KeyCode>>, mut bodies: Query<&mut Rigidbody>) {
-    let mut motion_vec = Vec2::ZERO;
-    if input.pressed(KeyCode::ArrowRight) {
-        motion_vec.x += 1.0;
-    }
-    if input.pressed(KeyCode::ArrowLeft) {
-        motion_vec.x -= 1.0
-    }
-    if input.pressed(KeyCode::ArrowUp) {
-        motion_vec.y += 1.0;
-    }
-    if input.pressed(KeyCode::ArrowDown) {
-        motion_vec.y -= 1.0
-    }
-
-    motion_vec *= 10.0;
-    for mut body in bodies.iter_mut() {
-        body.motion = motion_vec;
-    }
-}
-
 #[derive(Debug, Clone, Copy)]
 struct WorldAction {
     pub kind: WorldActionKind,
@@ -73,11 +64,16 @@ impl Default for WorldAction {
     }
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy)]
 enum WorldActionKind {
-    PlaceAir,
-    #[default]
-    PlaceTile,
+    PlaceTile(WorldTile),
+    Explode,
+}
+
+impl Default for WorldActionKind {
+    fn default() -> Self {
+        WorldActionKind::PlaceTile(WorldTile::Dirt)
+    }
 }
 
 impl WorldActionKind {
@@ -85,16 +81,23 @@ impl WorldActionKind {
         use WorldActionKind::*;
 
         match self {
-            PlaceAir => world.fill_radius(x, y, power, WorldTile::Air),
-            PlaceTile => world.fill_radius(x, y, power, WorldTile::Dirt),
+            PlaceTile(tile) => world.fill_radius(x, y, power, *tile),
+            Explode => {
+                let report = world.explode(x, y, power);
+                debug!("Explosion destroyed {} tiles", report.total_destroyed());
+            }
         }
     }
 
+    /// Cycles through all materials, starting with air, and then explosions
     fn next(self) -> Self {
         use WorldActionKind::*;
         match self {
-            PlaceAir => PlaceTile,
-            PlaceTile => PlaceAir,
+            PlaceTile(tile) => match WorldTile::ALL.get(tile as usize + 1) {
+                Some(next_tile) => PlaceTile(*next_tile),
+                None => Explode,
+            },
+            Explode => PlaceTile(WorldTile::ALL[0]),
         }
     }
 }
@@ -106,6 +109,7 @@ fn input(
     buttons: Res<ButtonInput<MouseButton>>,
     mut scroll_event_reader: EventReader<MouseWheel>,
     windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
 ) {
     if buttons.just_pressed(MouseButton::Right) {
         *action = action.next();
@@ -115,19 +119,90 @@ fn input(
         action.power = f32::max(action.power + event.y, 1.0);
     }
     if buttons.pressed(MouseButton::Left) {
-        let window = windows.single();
-        if let Some(mouse_pos) = window.cursor_position() {
-            let (x, y) = screen_coords_to_world(mouse_pos, window.height());
+        if let Some((x, y)) = cursor_tile(windows.single(), cameras.single()) {
             action.perform(&mut world.0, x, y);
         }
     }
 }
 
+/// This system spawns a bouncing ball at the cursor when pressing B and a capsule when pressing C
+fn spawn_bodies(
+    mut commands: Commands,
+    world: Res<GameWorld>,
+    input: Res<ButtonInput<KeyCode>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+) {
+    let collider = if input.just_pressed(KeyCode::KeyB) {
+        Collider::circle(1.0)
+    } else if input.just_pressed(KeyCode::KeyC) {
+        Collider::capsule(1.0, 0.5)
+    } else {
+        return;
+    };
+    let Some((x, y)) = cursor_tile(windows.single(), cameras.single()) else {
+        return;
+    };
+    let position = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+    // Bodies inside of the terrain would get stuck
+    let shape = collider.at(position);
+    let overlaps = match collider.shape {
+        ColliderShape::Circle { radius } => world.overlap_circle(shape.center, radius),
+        _ => world.overlap_rect(shape.bounds()),
+    };
+    if !overlaps.is_empty() {
+        return;
+    }
+    let position = position * TILE_SIZE;
+    commands.spawn((
+        SpatialBundle::from_transform(Transform::from_translation(position.extend(0.0))),
+        WorldTransform::default(),
+        Rigidbody {
+            restitution: 0.5,
+            ..default()
+        },
+        collider,
+        Intersection::default(),
+    ));
+}
+
+/// The file that the world gets saved to when pressing F5
+const SAVE_PATH: &str = "world.map";
+
+/// This system saves the world when F5 is pressed
+fn save_world(input: Res<ButtonInput<KeyCode>>, world: Res<GameWorld>) {
+    if !input.just_pressed(KeyCode::F5) {
+        return;
+    }
+    let result =
+        std::fs::File::create(SAVE_PATH).and_then(|file| world.save(std::io::BufWriter::new(file)));
+    match result {
+        Ok(()) => info!("Saved world to {SAVE_PATH}"),
+        Err(error) => error!("Could not save world to {SAVE_PATH}: {error}"),
+    }
+}
+
+/// The file that a preview image of the world gets saved to when pressing F6
+const IMAGE_PATH: &str = "world.png";
+
+/// This system exports the world as an image when F6 is pressed
+fn export_world_image(input: Res<ButtonInput<KeyCode>>, world: Res<GameWorld>) {
+    if !input.just_pressed(KeyCode::F6) {
+        return;
+    }
+    match world.save_image(IMAGE_PATH, &ImagePalette::default()) {
+        Ok(()) => info!("Exported world to {IMAGE_PATH}"),
+        Err(error) => error!("Could not export world to {IMAGE_PATH}: {error}"),
+    }
+}
+
 /// This system shows a debug outline around the currently selected block
-fn show_cursor_selection(mut gizmos: Gizmos, windows: Query<&Window, With<PrimaryWindow>>) {
-    let window = windows.single();
-    if let Some(mouse_pos) = window.cursor_position() {
-        let (x, y) = screen_coords_to_world(mouse_pos, window.height());
+fn show_cursor_selection(
+    mut gizmos: Gizmos,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+) {
+    if let Some((x, y)) = cursor_tile(windows.single(), cameras.single()) {
         gizmos.rect_2d(
             Vec2::new(x as f32 + 0.5, y as f32 + 0.5) * TILE_SIZE,
             0.,
@@ -137,8 +212,121 @@ fn show_cursor_selection(mut gizmos: Gizmos, windows: Query<&Window, With<Primar
     }
 }
 
-fn screen_coords_to_world(mut pos: Vec2, height: f32) -> (isize, isize) {
-    pos.y = height - pos.y;
-    pos /= TILE_SIZE;
-    (pos.x as isize, pos.y as isize)
+/// How far the players can see, in tiles
+const LINE_OF_SIGHT_DISTANCE: f32 = 100.0;
+
+/// This system shows whether the players can see the tile below the cursor.
+/// The line ends at the first tile that blocks the view.
+fn show_line_of_sight(
+    mut gizmos: Gizmos,
+    world: Res<GameWorld>,
+    players: Query<&Transform, With<Player>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+) {
+    let Some((x, y)) = cursor_tile(windows.single(), cameras.single()) else {
+        return;
+    };
+    let target = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+    for transform in players.iter() {
+        let origin = transform.translation.xy() / TILE_SIZE;
+        let distance = origin.distance(target).min(LINE_OF_SIGHT_DISTANCE);
+        match world.raycast(origin, target - origin, distance) {
+            Some(hit) => {
+                gizmos.line_2d(origin * TILE_SIZE, hit.point * TILE_SIZE, Color::ORANGE);
+                gizmos.line_2d(
+                    hit.point * TILE_SIZE,
+                    (hit.point + hit.normal) * TILE_SIZE,
+                    Color::ORANGE,
+                );
+            }
+            None => gizmos.line_2d(
+                origin * TILE_SIZE,
+                (origin + (target - origin).clamp_length_max(distance)) * TILE_SIZE,
+                Color::CYAN,
+            ),
+        }
+    }
+}
+
+/// This system shows the outline of all colliders
+fn show_colliders(mut gizmos: Gizmos, colliders: Query<(&Transform, &Collider)>) {
+    for (transform, collider) in colliders.iter() {
+        let shape = collider.at(transform.translation.xy() / TILE_SIZE);
+        let center = shape.center * TILE_SIZE;
+        match collider.shape {
+            ColliderShape::Aabb { half_extents } => {
+                let size = half_extents * 2.0 * TILE_SIZE;
+                gizmos.rect_2d(center, collider.rotation, size, Color::WHITE);
+            }
+            ColliderShape::Circle { radius } => {
+                gizmos.circle_2d(center, radius * TILE_SIZE, Color::WHITE);
+            }
+            ColliderShape::Capsule {
+                half_height,
+                radius,
+            } => {
+                let axis = shape.rotation.rotate(Vec2::Y * half_height) * TILE_SIZE;
+                let side = shape.rotation.rotate(Vec2::X * radius) * TILE_SIZE;
+                for side in [-side, side] {
+                    gizmos.line_2d(center + axis + side, center - axis + side, Color::WHITE);
+                }
+                for end in [axis, -axis] {
+                    gizmos.circle_2d(center + end, radius * TILE_SIZE, Color::WHITE);
+                }
+            }
+        }
+    }
+}
+
+/// This system shows where bodies touched the terrain and the tiles they are in contact with
+fn show_contacts(mut gizmos: Gizmos, intersections: Query<&Intersection>) {
+    for intersection in intersections.iter() {
+        if let Some(hit) = intersection.hit {
+            let point = hit.point * TILE_SIZE;
+            gizmos.circle_2d(point, TILE_SIZE / 4.0, Color::GREEN);
+            gizmos.line_2d(point, point + hit.normal * TILE_SIZE * 2.0, Color::GREEN);
+        }
+        for contact in &intersection.contacts {
+            let (x, y) = contact.tile;
+            let center = Vec2::new(x as f32 + 0.5, y as f32 + 0.5) * TILE_SIZE;
+            gizmos.rect_2d(center, 0.0, Vec2::splat(TILE_SIZE), Color::YELLOW);
+            gizmos.line_2d(center, center + contact.normal * TILE_SIZE, Color::YELLOW);
+        }
+    }
+}
+
+fn log_body_collisions(mut collisions: EventReader<BodyCollision>) {
+    for collision in collisions.read() {
+        let [a, b] = collision.entities;
+        debug!(
+            "{a:?} collided with {b:?} along {} with a depth of {}",
+            collision.normal, collision.depth
+        );
+    }
+}
+
+fn log_explosions(mut explosions: EventReader<Explosion>) {
+    for explosion in explosions.read() {
+        let target = match explosion.tank {
+            Some(tank) => format!("hit {tank:?}"),
+            None => "hit the terrain".to_owned(),
+        };
+        debug!(
+            "Shell {target} at {} with a power of {} and destroyed {} tiles",
+            explosion.position,
+            explosion.power,
+            explosion.report.total_destroyed()
+        );
+    }
+}
+
+/// Returns the coordinates of the tile below the cursor
+fn cursor_tile(
+    window: &Window,
+    (camera, camera_transform): (&Camera, &GlobalTransform),
+) -> Option<(isize, isize)> {
+    let cursor_pos = window.cursor_position()?;
+    let pos = camera.viewport_to_world_2d(camera_transform, cursor_pos)? / TILE_SIZE;
+    Some((pos.x.floor() as isize, pos.y.floor() as isize))
 }
@@ -1,33 +1,57 @@
+mod config;
+mod controls;
 mod debug_plugin;
 mod math;
 mod physics;
+mod projectile;
 mod tank;
 mod world;
 mod world_mesh;
 
-use bevy::{
-    prelude::*,
-    render::{
-        mesh::{Indices, MeshVertexAttribute},
-        render_asset::RenderAssetUsages,
-        render_resource::{PrimitiveTopology, VertexFormat},
-    },
-    sprite::Mesh2dHandle,
-};
+use std::{fs::File, io::BufReader};
+
+use bevy::{prelude::*, sprite::Mesh2dHandle, utils::HashMap};
+use config::{ConfigError, GameConfig, USAGE};
+use controls::{ControlsPlugin, Player};
 use debug_plugin::DebugPlugin;
 use physics::PhysicsPlugin;
-use tank::{TankBundle, TankPlugin};
-use world::World;
-use world_mesh::{WorldMesh2d, WorldMeshPlugin};
+use projectile::{ProjectilePlugin, Wind};
+use tank::{Tank, TankBundle, TankPlugin};
+use world::{image_map::ImagePalette, World, WorldGenConfig};
+use world_mesh::{mesher::gen_chunk_mesh, WorldMesh2d, WorldMeshPlugin};
 
-#[derive(Resource)]
-pub struct WorldMesh(Mesh2dHandle);
+/// The mesh entities of all chunks that contain visible tiles, by chunk position
+#[derive(Resource, Default)]
+pub struct WorldMeshes(HashMap<(usize, usize), (Entity, Mesh2dHandle)>);
 
 #[derive(Resource, Deref, DerefMut)]
 pub struct GameWorld(World);
 
 fn main() {
-    let window_resolution = (WIDTH as f32 * TILE_SIZE, HEIGHT as f32 * TILE_SIZE).into();
+    let mut config = match GameConfig::from_args(std::env::args().skip(1)) {
+        Ok(config) => config,
+        Err(ConfigError::HelpRequested) => {
+            println!("{USAGE}");
+            return;
+        }
+        Err(error) => {
+            eprintln!("{error}\n\n{USAGE}");
+            std::process::exit(1);
+        }
+    };
+    if config.map.is_none() && config.seed.is_none() {
+        config.seed = Some(random_seed());
+    }
+    let world = match load_world(&config) {
+        Ok(world) => world,
+        Err(error) => {
+            eprintln!("Could not create the world: {error}");
+            std::process::exit(1);
+        }
+    };
+
+    let world_size = Vec2::new(world.width as f32, world.height as f32) * config.tile_size;
+    let window_resolution = world_size.min(MAX_WINDOW_SIZE).into();
     App::new()
         .add_plugins((
             DefaultPlugins
@@ -40,120 +64,175 @@ fn main() {
                 })
                 .set(ImagePlugin::default_nearest()),
             WorldMeshPlugin,
-            PhysicsPlugin,
+            PhysicsPlugin {
+                tick_rate: config.physics_rate,
+            },
+            ControlsPlugin,
             TankPlugin,
+            ProjectilePlugin,
             DebugPlugin,
         ))
-        .insert_resource(GameWorld(World::generate(WIDTH, HEIGHT)))
-        .insert_resource(ClearColor(Color::rgb(0.5, 0.8, 0.99)))
+        .insert_resource(GameWorld(world))
+        .init_resource::<WorldMeshes>()
+        .insert_resource(ClearColor(config.clear_color()))
+        .insert_resource(Wind(Vec2::X * config.wind))
+        .insert_resource(config)
         .add_systems(Startup, setup)
-        .add_systems(Update, update_world_mesh)
+        .add_systems(Update, (update_world_mesh, follow_tank_with_camera))
+        .add_systems(
+            FixedUpdate,
+            (simulate_world, collapse_floating_islands).chain(),
+        )
         .run();
 }
 
-const WIDTH: usize = 200;
-const HEIGHT: usize = 100;
+/// The size of a tile in world units.
+/// The size on the screen is configured by [`GameConfig::tile_size`].
 const TILE_SIZE: f32 = 8.0;
+/// The window is only as large as the world if the world fits into this size
+const MAX_WINDOW_SIZE: Vec2 = Vec2::new(1600.0, 800.0);
+
+/// Returns a different seed on every launch
+fn random_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_nanos() as u64)
+}
 
-fn setup(mut commands: Commands, meshes: Res<Assets<Mesh>>) {
-    let world_mesh_handle = Mesh2dHandle(meshes.reserve_handle());
-    commands.insert_resource(WorldMesh(world_mesh_handle.clone()));
-    commands.spawn((
-        WorldMesh2d,
-        world_mesh_handle,
-        // This bundle's components are needed for something to be rendered
-        SpatialBundle::INHERITED_IDENTITY,
-    ));
+/// Loads the configured map, or generates a new world if there is none
+fn load_world(config: &GameConfig) -> Result<World, Box<dyn std::error::Error>> {
+    let Some(path) = &config.map else {
+        return Ok(World::generate(&WorldGenConfig {
+            width: config.width,
+            height: config.height,
+            seed: config.seed.unwrap_or_default(),
+            ..default()
+        }));
+    };
+    let is_image = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("png"));
+    if is_image {
+        Ok(World::load_image(path, &ImagePalette::default())?)
+    } else {
+        Ok(World::load(BufReader::new(File::open(path)?))?)
+    }
+}
+
+fn setup(mut commands: Commands, config: Res<GameConfig>, world: Res<GameWorld>) {
+    match (&config.map, config.seed) {
+        (Some(map), _) => info!("Playing on map {}", map.display()),
+        (None, Some(seed)) => info!("Generated world with seed {seed}"),
+        (None, None) => {}
+    }
 
-    commands.spawn(TankBundle {
-        spatial_bundle: SpatialBundle {
-            transform: Transform::from_xyz(250.0, 250.0, 0.0).with_scale(Vec3::splat(8.0)),
+    // Without a configured spawn, the tank falls down onto the terrain from slightly above it
+    let tank_spawn = config.tank_spawn.map_or_else(
+        || {
+            let x = world.width as isize / 6;
+            Vec2::new(x as f32, (world.surface_height(x) + 8) as f32)
+        },
+        Vec2::from,
+    ) * TILE_SIZE;
+    commands.spawn((
+        TankBundle {
+            spatial_bundle: SpatialBundle {
+                transform: Transform::from_translation(tank_spawn.extend(0.0)),
+                ..default()
+            },
             ..default()
         },
-        ..default()
-    });
+        Player,
+    ));
 
     // Spawn the camera
-    commands.spawn(Camera2dBundle {
+    let mut camera = Camera2dBundle {
         transform: Transform::from_translation(Vec3::new(
-            WIDTH as f32 / 2.0 * TILE_SIZE,
-            HEIGHT as f32 / 2.0 * TILE_SIZE,
+            world.width as f32 / 2.0 * TILE_SIZE,
+            world.height as f32 / 2.0 * TILE_SIZE,
             0.0,
         )),
         ..default()
-    });
+    };
+    camera.projection.scale = TILE_SIZE / config.tile_size;
+    commands.spawn(camera);
 }
 
-/// This system updates the world mesh whenever the world has changed
-fn update_world_mesh(
-    world: Res<GameWorld>,
-    world_mesh_handle: Option<Res<WorldMesh>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-) {
-    if !world.is_changed() {
+/// This system lets loose tiles fall down
+fn simulate_world(mut world: ResMut<GameWorld>) {
+    // Avoid triggering change detection if there is nothing to simulate
+    if world.is_settled() {
         return;
     }
+    world.simulate_step();
+}
 
-    trace!("World has changed!");
+/// This system lets terrain that is not connected to the ground fall down
+fn collapse_floating_islands(mut world: ResMut<GameWorld>) {
+    if !world.is_changed() {
+        return;
+    }
+    // Only trigger change detection if an island actually moved
+    if world.bypass_change_detection().drop_floating_islands() {
+        world.set_changed();
+    }
+}
 
-    let Some(world_mesh_handle) = world_mesh_handle else {
+/// This system keeps the tank centered on the screen without showing anything outside of the world
+fn follow_tank_with_camera(
+    world: Res<GameWorld>,
+    tanks: Query<&Transform, (With<Tank>, Without<Camera>)>,
+    mut cameras: Query<(&mut Transform, &OrthographicProjection), With<Camera>>,
+) {
+    let Some(tank_transform) = tanks.iter().next() else {
         return;
     };
-
-    let mesh = gen_world_mesh(&world.0);
-    meshes.insert(&world_mesh_handle.0 .0, mesh);
+    let world_size = Vec2::new(world.width as f32, world.height as f32) * TILE_SIZE;
+    for (mut transform, projection) in cameras.iter_mut() {
+        let half_view_size = projection.area.half_size();
+        let min = half_view_size.min(world_size / 2.0);
+        let max = (world_size - half_view_size).max(world_size / 2.0);
+        let target = tank_transform.translation.xy().clamp(min, max);
+        transform.translation.x = target.x;
+        transform.translation.y = target.y;
+    }
 }
 
-/// Builds a mesh from the world
-fn gen_world_mesh(world: &World) -> Mesh {
-    let mut world_mesh = Mesh::new(
-        PrimitiveTopology::TriangleList,
-        RenderAssetUsages::RENDER_WORLD,
-    );
-
-    let mut v_pos = Vec::with_capacity(WIDTH * HEIGHT * 4);
-    let mut v_local_pos = Vec::with_capacity(WIDTH * HEIGHT * 4);
-    let mut v_color = Vec::with_capacity(WIDTH * HEIGHT * 4);
-    let mut v_neighbors = Vec::with_capacity(WIDTH * HEIGHT * 4);
-    let mut indices = Vec::with_capacity(WIDTH * HEIGHT * 6);
-    for y in 0..HEIGHT {
-        for x in 0..WIDTH {
-            let cx = x as f32 * TILE_SIZE;
-            let cy = y as f32 * TILE_SIZE;
-            let nx = (x + 1) as f32 * TILE_SIZE;
-            let ny = (y + 1) as f32 * TILE_SIZE;
-            let index = v_pos.len() as u32;
-            v_pos.extend([[cx, cy, 0.0], [nx, cy, 0.0], [nx, ny, 0.0], [cx, ny, 0.0]]);
-            v_local_pos.extend([[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]);
-            indices.extend([index, index + 1, index + 2, index + 2, index + 3, index]);
-            v_color.extend([Color::GOLD.as_linear_rgba_u32(); 4]);
-
-            let top = world.get(x as isize, y as isize + 1).is_not_air() as u32;
-            let left = world.get(x as isize - 1, y as isize).is_not_air() as u32;
-            let bottom = world.get(x as isize, y as isize - 1).is_not_air() as u32;
-            let right = world.get(x as isize + 1, y as isize).is_not_air() as u32;
-            let self_on = world.get(x as isize, y as isize).is_not_air() as u32;
-            let neighbors_bitset = top | left << 1 | bottom << 2 | right << 3 | self_on << 4;
-            v_neighbors.extend([neighbors_bitset; 4]);
+/// This system rebuilds the meshes of all chunks that have changed
+fn update_world_mesh(
+    mut commands: Commands,
+    mut world: ResMut<GameWorld>,
+    mut world_meshes: ResMut<WorldMeshes>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    // Resetting the dirty chunks is not a modification of the world itself
+    let dirty_chunks = world.bypass_change_detection().take_dirty_chunks();
+    for chunk_pos in dirty_chunks {
+        trace!("Chunk {chunk_pos:?} has changed!");
+        let mesh = gen_chunk_mesh(&world, chunk_pos);
+        match world_meshes.0.get(&chunk_pos) {
+            // Empty meshes can't be rendered, so chunks without visible tiles don't get an entity
+            Some((entity, handle)) if mesh.count_vertices() == 0 => {
+                commands.entity(*entity).despawn();
+                meshes.remove(&handle.0);
+                world_meshes.0.remove(&chunk_pos);
+            }
+            Some((_, handle)) => {
+                meshes.insert(&handle.0, mesh);
+            }
+            None if mesh.count_vertices() == 0 => {}
+            None => {
+                let handle = Mesh2dHandle(meshes.add(mesh));
+                let entity = commands
+                    .spawn((
+                        WorldMesh2d,
+                        handle.clone(),
+                        // This bundle's components are needed for something to be rendered
+                        SpatialBundle::INHERITED_IDENTITY,
+                    ))
+                    .id();
+                world_meshes.0.insert(chunk_pos, (entity, handle));
+            }
         }
     }
-
-    // Set the position attribute
-    world_mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, v_pos);
-    // And a RGB color attribute as well
-    world_mesh.insert_attribute(
-        MeshVertexAttribute::new("Vertex_Color", 1, VertexFormat::Uint32),
-        v_color,
-    );
-    world_mesh.insert_attribute(
-        MeshVertexAttribute::new("Vertex_LocalPos", 2, VertexFormat::Float32x2),
-        v_local_pos,
-    );
-    world_mesh.insert_attribute(
-        MeshVertexAttribute::new("Vertex_Neighbors", 3, VertexFormat::Uint32),
-        v_neighbors,
-    );
-    world_mesh.insert_indices(Indices::U32(indices));
-
-    world_mesh
 }
@@ -2,26 +2,6 @@ use std::cmp::Ordering;
 
 use bevy::prelude::*;
 
-/// Calculates the shortest vector from the line to the point
-/// The line is assumed to originate at (0|0) and to go to the point defined by `line`
-pub fn vector_line_point(line: Vec2, point: Vec2) -> Vec2 {
-    let vec_origin_point = point;
-    let vec_line_end_point = point - line;
-
-    if line == Vec2::ZERO {
-        return vec_origin_point;
-    }
-
-    // t is the point on the line defined as `line * t` with the smallest distance to the target point
-    let t = (line.x * point.x + line.y * point.y) / (line.x.powi(2) + line.y.powi(2));
-    if t <= 0.0 || t >= 1.0 {
-        return min_by_key(vec_origin_point, vec_line_end_point, |vector| {
-            vector.length()
-        });
-    }
-    point - (line * t)
-}
-
 pub fn min_by_key<T, U, F>(a: T, b: T, mut function: F) -> T
 where
     F: FnMut(&T) -> U,
@@ -35,15 +15,52 @@ where
     }
 }
 
-pub fn max_by_key<T, U, F>(a: T, b: T, mut function: F) -> T
-where
-    F: FnMut(&T) -> U,
-    U: PartialOrd,
-{
-    let val_a = function(&a);
-    let val_b = function(&b);
-    match val_a.partial_cmp(&val_b) {
-        Some(Ordering::Greater | Ordering::Equal) | None => a,
-        Some(Ordering::Less) => b,
+/// A small and fast pseudo random number generator (SplitMix64).
+/// The same seed always results in the same sequence of numbers on every platform.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
     }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in the range `[0, 1)`
+    pub fn next_f32(&mut self) -> f32 {
+        // Use the upper 24 bits, which is exactly the precision of a f32
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Returns a number in the range `[min, max)`
+    pub fn range_f32(&mut self, min: f32, max: f32) -> f32 {
+        min + self.next_f32() * (max - min)
+    }
+}
+
+/// Smooth 2d value noise in the range `[0, 1]`.
+/// The noise has features that are roughly `1.0` apart, so scale the coordinates to change the feature size.
+pub fn value_noise_2d(seed: u64, pos: Vec2) -> f32 {
+    let cell = pos.floor();
+    let local = pos - cell;
+    // Smoothstep, so that the noise has no visible edges at cell borders
+    let t = local * local * (Vec2::splat(3.0) - 2.0 * local);
+    let corner = |dx: f32, dy: f32| {
+        let x = (cell.x + dx) as i64 as u64;
+        let y = (cell.y + dy) as i64 as u64;
+        Rng::new(seed ^ x.wrapping_mul(0x9E37_79B9) ^ y.wrapping_mul(0x85EB_CA6B_C2B2_AE35))
+            .next_f32()
+    };
+    let bottom = corner(0.0, 0.0) + (corner(1.0, 0.0) - corner(0.0, 0.0)) * t.x;
+    let top = corner(0.0, 1.0) + (corner(1.0, 1.0) - corner(0.0, 1.0)) * t.x;
+    bottom + (top - bottom) * t.y
 }
@@ -1,26 +1,133 @@
 use bevy::prelude::*;
 
+use self::{
+    body_collision::{collide_bodies, BodyCollision},
+    collider::{Collider, RoundedRect},
+};
 use crate::{
-    math::{self, max_by_key},
+    math::min_by_key,
+    world::{RenderedWorldTile, World},
     GameWorld, TILE_SIZE,
 };
 
+pub mod body_collision;
+pub mod collider;
+
 /// Component that moves entities every physics step
-#[derive(Component, Debug, Default)]
+#[derive(Component, Debug)]
 pub struct Rigidbody {
-    pub motion: Vec2,
+    /// The velocity in tiles per second
+    pub velocity: Vec2,
+    /// The acceleration in tiles per second² that the body causes itself, e.g. by driving.
+    /// [`Gravity`] is applied in addition to this.
+    pub acceleration: Vec2,
+    pub mass: f32,
+    /// The friction coefficient, which slows down movement along the terrain.
+    /// A body with a friction of 1 comes to rest on slopes of up to 45°.
+    pub friction: f32,
+    /// How much of the velocity into the terrain is reflected on impact, between 0 and 1
+    pub restitution: f32,
 }
 
-/// Entities with this component and a [`Intersection`] and ['GlobalTransform`] component will have collision detection
-#[derive(Component, Debug)]
-pub struct Collider;
+impl Default for Rigidbody {
+    fn default() -> Self {
+        Self {
+            velocity: Vec2::ZERO,
+            acceleration: Vec2::ZERO,
+            mass: 1.0,
+            friction: 0.5,
+            restitution: 0.0,
+        }
+    }
+}
+
+impl Rigidbody {
+    /// Removes the velocity into a surface with the given normal,
+    /// reflecting it by the restitution and slowing down the movement along the surface by friction
+    fn bounce_off(&mut self, normal: Vec2) {
+        let normal_speed = self.velocity.dot(normal);
+        if normal_speed >= 0.0 {
+            return;
+        }
+        let mut tangent_velocity = self.velocity - normal * normal_speed;
+        // Bodies that accelerate in the direction they are moving drive along the surface
+        // instead of sliding over it, so they are not slowed down
+        if self.acceleration.dot(tangent_velocity) <= 0.0 {
+            // Coulomb friction: the slowdown is proportional to how hard the body hits the surface
+            let friction_speed = -normal_speed * self.friction;
+            tangent_velocity = tangent_velocity
+                .clamp_length_max((tangent_velocity.length() - friction_speed).max(0.0));
+        }
+        self.velocity = tangent_velocity - normal * normal_speed * self.restitution;
+    }
+
+    /// Changes the velocity of this body by an instantaneous impulse, taking the mass into account
+    pub fn apply_impulse(&mut self, impulse: Vec2) {
+        self.velocity += impulse / self.mass;
+    }
+}
+
+/// The acceleration in tiles per second² that pulls all rigidbodies down
+#[derive(Resource, Debug, Clone, Copy, Deref, DerefMut)]
+pub struct Gravity(pub Vec2);
+
+impl Default for Gravity {
+    fn default() -> Self {
+        Self(Vec2::new(0.0, -30.0))
+    }
+}
+
+/// Whether the entity is standing on terrain, updated every physics step
+#[derive(Component, Debug, Default)]
+pub struct Grounded(pub bool);
+
+/// Bodies are grounded if the terrain pushes them in a direction that is at most this steep,
+/// as the y component of the unit vector. 0.5 allows slopes of up to 60°.
+const MIN_GROUND_NORMAL_Y: f32 = 0.5;
 
 /// Describes the current intersection of the entity with the world
 #[derive(Component, Debug, Default)]
 pub struct Intersection {
+    /// The vector that moves the entity out of all tiles that it intersects
     pub correction: Vec2,
+    /// Every tile that the entity intersected before the correction was applied
+    pub contacts: Vec<Contact>,
+    /// Where the entity first touched the terrain while moving during the last physics step
+    pub hit: Option<Hit>,
+}
+
+/// The point where a moving body touched the terrain
+#[derive(Debug, Clone, Copy)]
+pub struct Hit {
+    /// The point on the body that touched the terrain, in tiles
+    pub point: Vec2,
+    /// The unit vector that points away from the terrain
+    pub normal: Vec2,
+}
+
+/// An intersection of a body with a single tile
+#[derive(Debug, Clone, Copy)]
+pub struct Contact {
+    /// The unit vector that points away from the tile
+    pub normal: Vec2,
+    /// How far the body has to move along the normal to stop intersecting the tile, in tiles
+    pub depth: f32,
+    /// The position of the tile
+    pub tile: (isize, isize),
 }
 
+/// How often the contacts are resolved and searched again in a single physics step.
+/// Resolving contacts can push a body into other tiles, which are found in the next iteration.
+const RESOLVE_ITERATIONS: usize = 4;
+
+/// The largest distance in tiles that a body moves before checking for collisions again.
+/// This has to be smaller than the thinnest terrain, which is a diamond of half a tile.
+const MAX_SWEEP_STEP: f32 = 0.25;
+/// How often the position of a hit is refined after finding the step in which it happened
+const SWEEP_REFINEMENTS: usize = 8;
+/// How often a body may slide along the terrain after hitting it during one physics step
+const MAX_SLIDES: usize = 3;
+
 /// A transform in world coordinates
 #[derive(Component, Debug, Default)]
 pub struct WorldTransform {
@@ -28,48 +135,127 @@ pub struct WorldTransform {
     pub tile_position: (isize, isize),
 }
 
-pub struct PhysicsPlugin;
+impl WorldTransform {
+    pub fn from_translation(translation: Vec2) -> Self {
+        Self {
+            translation,
+            tile_position: (translation.x as isize, translation.y as isize),
+        }
+    }
+}
+
+/// Runs the physics in fixed steps, so that it behaves the same at every frame rate.
+/// Systems that control bodies should run in `FixedUpdate`, right before the physics step.
+/// Systems that react to the result of a step can run in `FixedPostUpdate` after [`PhysicsStep`].
+pub struct PhysicsPlugin {
+    /// How many physics steps run per second
+    pub tick_rate: f64,
+}
 
 impl Plugin for PhysicsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            FixedPostUpdate,
-            ((
-                apply_motion,
-                (set_world_transform, reset_intersections),
-                collide_with_world,
-                apply_corrections,
-            )
-                .chain(),),
-        );
+        app.insert_resource(Time::<Fixed>::from_hz(self.tick_rate))
+            .init_resource::<Gravity>()
+            .add_event::<BodyCollision>()
+            .add_systems(
+                FixedPostUpdate,
+                ((
+                    reset_intersections,
+                    apply_motion,
+                    set_world_transform,
+                    // Bodies may get pushed into the terrain, so the terrain is resolved afterwards
+                    collide_bodies,
+                    collide_with_world,
+                    (apply_corrections, update_grounded),
+                )
+                    .chain()
+                    .in_set(PhysicsStep),),
+            );
     }
 }
 
+/// The systems that move the bodies and resolve their collisions during a physics step
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PhysicsStep;
+
 fn set_world_transform(mut query: Query<(&Transform, &mut WorldTransform)>) {
     for (transform, mut world_transform) in query.iter_mut() {
-        let mut world_pos = transform.translation.xy();
-        world_pos /= TILE_SIZE;
-        *world_transform = WorldTransform {
-            translation: world_pos,
-            tile_position: (world_pos.x as isize, world_pos.y as isize),
-        }
+        let world_pos = transform.translation.xy() / TILE_SIZE;
+        *world_transform = WorldTransform::from_translation(world_pos);
     }
 }
 
-fn apply_motion(time: Res<Time>, mut query: Query<(&mut Transform, &Rigidbody)>) {
-    for (mut transform, body) in query.iter_mut() {
-        transform.translation.x += body.motion.x * TILE_SIZE * time.delta_seconds();
-        transform.translation.y += body.motion.y * TILE_SIZE * time.delta_seconds();
+/// This system moves bodies by their velocity.
+/// Bodies with a collider are swept through the world, so they stop at the terrain even when
+/// they move further than its thickness during a single step.
+fn apply_motion(
+    time: Res<Time<Fixed>>,
+    gravity: Res<Gravity>,
+    world: Res<GameWorld>,
+    mut query: Query<(
+        &mut Transform,
+        &mut Rigidbody,
+        Option<&mut Intersection>,
+        Option<&Collider>,
+    )>,
+) {
+    // Every step has the same length, so the result doesn't depend on how long the frames take
+    let delta = time.timestep().as_secs_f32();
+    for (mut transform, mut body, mut intersection, collider) in query.iter_mut() {
+        let acceleration = body.acceleration + gravity.0;
+        body.velocity += acceleration * delta;
+        let mut motion = body.velocity * delta;
+        let Some(collider) = collider else {
+            transform.translation += (motion * TILE_SIZE).extend(0.0);
+            continue;
+        };
+
+        let start = transform.translation.xy() / TILE_SIZE;
+        let mut shape = collider.at(start);
+        for _ in 0..MAX_SLIDES {
+            let (moved, hit) = sweep_shape(&world, shape, motion);
+            shape = shape.translated(moved);
+            let Some(hit) = hit else {
+                break;
+            };
+            if let Some(intersection) = &mut intersection {
+                intersection.hit.get_or_insert(hit);
+            }
+            body.bounce_off(hit.normal);
+            // Slide along the terrain with the rest of the motion
+            let remaining = motion - moved;
+            motion = remaining - hit.normal * remaining.dot(hit.normal).min(0.0);
+        }
+        let moved = shape.center - collider.at(start).center;
+        transform.translation += (moved * TILE_SIZE).extend(0.0);
     }
 }
 
-fn apply_corrections(mut query: Query<(&mut Transform, &Intersection)>) {
-    for (mut transform, intersection) in query.iter_mut() {
-        if intersection.correction == Vec2::ZERO {
-            continue;
-        }
+fn apply_corrections(mut query: Query<(&mut Transform, &Intersection, Option<&mut Rigidbody>)>) {
+    for (mut transform, intersection, body) in query.iter_mut() {
         transform.translation.x += intersection.correction.x * TILE_SIZE;
         transform.translation.y += intersection.correction.y * TILE_SIZE;
+
+        if let Some(mut body) = body {
+            for contact in &intersection.contacts {
+                body.bounce_off(contact.normal);
+            }
+        }
+    }
+}
+
+fn update_grounded(mut query: Query<(&Intersection, &mut Grounded)>) {
+    for (intersection, mut grounded) in query.iter_mut() {
+        let is_grounded = intersection
+            .hit
+            .iter()
+            .map(|hit| hit.normal)
+            .chain(intersection.contacts.iter().map(|contact| contact.normal))
+            .any(|normal| normal.y >= MIN_GROUND_NORMAL_Y);
+        // Only trigger change detection when the state actually changes
+        if grounded.0 != is_grounded {
+            grounded.0 = is_grounded;
+        }
     }
 }
 
@@ -81,36 +267,122 @@ fn reset_intersections(mut intersections: Query<&mut Intersection>) {
 
 fn collide_with_world(
     world: Res<GameWorld>,
-    mut query: Query<(&WorldTransform, &Transform, &mut Intersection), With<Collider>>,
+    mut query: Query<(&WorldTransform, &Collider, &mut Intersection)>,
     mut gizmos: Gizmos,
 ) {
-    for (world_transform, transform, mut intersection) in query.iter_mut() {
-        let collider_rect =
-            Rect::from_center_size(world_transform.translation, transform.scale.xy());
-
-        let mut max_correction = Vec2::ZERO;
-        let possible_collisions = world.get_rendered_in_rect(collider_rect);
-        for world_tile in possible_collisions {
-            if !world_tile.tile.has_collider() {
-                continue;
+    for (world_transform, collider, mut intersection) in query.iter_mut() {
+        let shape = collider.at(world_transform.translation);
+        let contacts = world_contacts(&world, shape, |line| {
+            gizmos.line_2d(line.start * TILE_SIZE, line.end * TILE_SIZE, Color::RED);
+        });
+
+        let mut correction = Vec2::ZERO;
+        let mut remaining_contacts = contacts.clone();
+        for _ in 0..RESOLVE_ITERATIONS {
+            if remaining_contacts.is_empty() {
+                break;
             }
+            correction += combine_contacts(&remaining_contacts);
+            remaining_contacts = world_contacts(&world, shape.translated(correction), |_| {});
+        }
 
-            let lines = get_tile_lines(world_tile.pos.0, world_tile.pos.1);
-            for line in lines {
-                if let Some(correction) = line.collide_rect(collider_rect) {
-                    gizmos.line_2d(line.start * TILE_SIZE, line.end * TILE_SIZE, Color::RED);
-                    max_correction =
-                        max_by_key(max_correction, correction, |vector| vector.length());
-                }
+        intersection.correction = correction;
+        intersection.contacts = contacts;
+    }
+}
+
+/// Returns a contact for every tile that intersects the shape.
+/// `on_collision` is called with every line that intersects the shape.
+fn world_contacts(
+    world: &World,
+    shape: RoundedRect,
+    mut on_collision: impl FnMut(Line),
+) -> Vec<Contact> {
+    let mut contacts = Vec::new();
+    let possible_collisions = world.get_rendered_in_rect(shape.bounds());
+    for world_tile in possible_collisions {
+        if !world_tile.tile.has_collider() {
+            continue;
+        }
+
+        // The smallest correction moves the shape out of this tile
+        let mut tile_correction: Option<Vec2> = None;
+        for line in get_tile_lines(&world_tile) {
+            if let Some(correction) = line.collide_shape(shape) {
+                on_collision(line);
+                tile_correction = Some(match tile_correction {
+                    Some(other) => min_by_key(other, correction, |vector| vector.length()),
+                    None => correction,
+                });
             }
         }
+        if let Some(correction) = tile_correction {
+            contacts.push(Contact {
+                normal: correction.normalize(),
+                depth: correction.length(),
+                tile: world_tile.pos,
+            });
+        }
+    }
+    contacts
+}
+
+/// Combines the corrections of all contacts per axis.
+///
+/// On each axis, the shape is moved by the largest correction in each direction, so that a body
+/// touching the ground and a wall is pushed out of both at once instead of only the deeper one.
+fn combine_contacts(contacts: &[Contact]) -> Vec2 {
+    let mut positive = Vec2::ZERO;
+    let mut negative = Vec2::ZERO;
+    for contact in contacts {
+        let correction = contact.normal * contact.depth;
+        positive = positive.max(correction);
+        negative = negative.min(correction);
+    }
+    positive + negative
+}
 
-        if max_correction != Vec2::ZERO {
-            intersection.correction = max_correction;
+/// Moves the shape along `motion` until it touches the terrain.
+/// Returns the motion up to that point and the hit, or the full motion if nothing was hit.
+///
+/// Shapes that already intersect the terrain are moved freely, because they are pushed out by
+/// [`collide_with_world`] afterwards.
+pub fn sweep_shape(world: &World, shape: RoundedRect, motion: Vec2) -> (Vec2, Option<Hit>) {
+    let contacts_at =
+        |fraction: f32| world_contacts(world, shape.translated(motion * fraction), |_| {});
+    if !contacts_at(0.0).is_empty() {
+        return (motion, None);
+    }
+
+    let steps = (motion.length() / MAX_SWEEP_STEP).ceil().max(1.0) as usize;
+    let Some(hit_step) =
+        (1..=steps).find(|step| !contacts_at(*step as f32 / steps as f32).is_empty())
+    else {
+        return (motion, None);
+    };
+    // Narrow down the fraction of the motion at which the shape starts to intersect the terrain
+    let mut free = (hit_step - 1) as f32 / steps as f32;
+    let mut blocked = hit_step as f32 / steps as f32;
+    for _ in 0..SWEEP_REFINEMENTS {
+        let middle = (free + blocked) / 2.0;
+        if contacts_at(middle).is_empty() {
+            free = middle;
+        } else {
+            blocked = middle;
         }
     }
+
+    // The deepest contact is the one that was hit first
+    let normal = contacts_at(blocked)
+        .into_iter()
+        .max_by(|a, b| a.depth.total_cmp(&b.depth))
+        .map_or(Vec2::ZERO, |contact| contact.normal);
+    let point = shape.translated(motion * free).support_point(-normal);
+    (motion * free, Some(Hit { point, normal }))
 }
 
+/// A collision edge of a tile.
+/// The solid side is to the right of the line, so going from `start` to `end` walks clockwise around the tile.
 #[derive(Debug, Clone, Copy)]
 struct Line {
     pub start: Vec2,
@@ -122,36 +394,207 @@ impl Line {
         Line { start, end }
     }
 
-    /// Tests for a collision and returns the correction vector is there is some
-    pub fn collide_rect(self, rect: Rect) -> Option<Vec2> {
-        let rect_center = rect.center();
-        let distance_vector =
-            math::vector_line_point(self.end - self.start, rect_center - self.start);
-        let delta = rect.half_size() - distance_vector.abs();
-        if delta.x > 0.0 && delta.y > 0.0 {
-            let perp = self.dir().perp();
-            // Determine the smallest amount the rect has to be moved to not collide anymore
-            let factor = match perp {
-                Vec2 { x, y } if x == 0.0 => delta.y / y,
-                Vec2 { x, y } if y == 0.0 => delta.x / x,
-                perp => (delta / perp).min_element(),
-            };
-            return Some(factor * perp);
+    /// Tests for a collision and returns the correction vector if there is some.
+    ///
+    /// The shape is always pushed out to the outer side of the line, so that it can't get stuck
+    /// on the inside of the terrain.
+    pub fn collide_shape(self, shape: RoundedRect) -> Option<Vec2> {
+        // The line and the shape are separated if they don't overlap on one of the axes of the shape
+        if !shape.overlaps_segment_bounds(self.start, self.end) {
+            return None;
+        }
+
+        // ... or if the shape is completely on one side of the line
+        let normal = self.normal();
+        let extent = shape.extent(normal);
+        let distance = (shape.center - self.start).dot(normal);
+        if distance - extent >= 0.0 || distance + extent <= 0.0 {
+            return None;
+        }
+        // Boxes are separated on one of these axes, but rounded corners can be close to the
+        // end of a line without touching it
+        if shape.radius > 0.0 && !shape.intersects_segment(self.start, self.end) {
+            return None;
         }
-        None
+
+        // Rotated shapes are often pushed out along one of their own axes with a shorter distance,
+        // e.g. when a corner of the terrain pokes into one of their sides
+        let mut correction = normal * (extent - distance);
+        for axis in shape.axes() {
+            // Always push to the outer side of the line
+            let direction = axis * axis.dot(normal).signum();
+            if direction.dot(normal) <= 0.0 {
+                continue;
+            }
+            let distance = [self.start, self.end]
+                .map(|point| (shape.center - point).dot(direction))
+                .into_iter()
+                .fold(f32::INFINITY, f32::min);
+            let depth = shape.extent(direction) - distance;
+            if depth <= 0.0 {
+                // The shape only touches the line
+                return None;
+            }
+            correction = min_by_key(correction, direction * depth, |vector| vector.length());
+        }
+        Some(correction)
     }
 
     /// The direction vector of this line
     pub fn dir(self) -> Vec2 {
         self.end - self.start
     }
+
+    /// The unit vector that points away from the solid side of this line
+    pub fn normal(self) -> Vec2 {
+        self.dir().perp().normalize()
+    }
 }
 
-fn get_tile_lines(x: isize, y: isize) -> [Line; 1] {
-    let x = x as f32;
-    let y = y as f32;
-    [Line::new(
-        Vec2::new(x, y + 1.0),
-        Vec2::new(x + 1.0, y + 1.0),
-    )]
+/// Returns the edges of the tile that separate it from non-solid space, or none if it has no collider.
+///
+/// The edges follow the shape of the tile, so tiles collide exactly where they are drawn.
+fn get_tile_lines(world_tile: &RenderedWorldTile) -> Vec<Line> {
+    if !world_tile.tile.has_collider() {
+        return Vec::new();
+    }
+    let (x, y) = world_tile.pos;
+    let offset = Vec2::new(x as f32, y as f32);
+    world_tile
+        .shape()
+        .edges()
+        .into_iter()
+        .map(|(start, end)| Line::new(offset + start, offset + end))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+    use crate::world::WorldTile;
+
+    fn physics_app(world: World) -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<Shader>()
+            .add_plugins((bevy::gizmos::GizmoPlugin, PhysicsPlugin { tick_rate: 64.0 }))
+            .insert_resource(GameWorld(world))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+                1.0 / 64.0,
+            )));
+        app
+    }
+
+    /// Spawns a body at the given position in tiles
+    fn spawn_body(app: &mut App, position: Vec2, velocity: Vec2, collider: Collider) -> Entity {
+        let translation = (position * TILE_SIZE).extend(0.0);
+        app.world
+            .spawn((
+                Transform::from_translation(translation),
+                WorldTransform::from_translation(position),
+                Rigidbody {
+                    velocity,
+                    ..default()
+                },
+                collider,
+                Intersection::default(),
+                Grounded::default(),
+            ))
+            .id()
+    }
+
+    fn position(app: &App, body: Entity) -> Vec2 {
+        app.world.get::<Transform>(body).unwrap().translation.xy() / TILE_SIZE
+    }
+
+    #[test]
+    fn fast_bodies_dont_pass_through_thin_walls() {
+        let world = World::filled(60, 40, |x, _| {
+            if x == 30 {
+                WorldTile::Stone
+            } else {
+                WorldTile::Air
+            }
+        });
+        let mut app = physics_app(world);
+        app.insert_resource(Gravity(Vec2::ZERO));
+        // Both bodies move further than the whole world is wide during a single step
+        let bodies = [Collider::circle(0.4), Collider::aabb(Vec2::splat(0.4))].map(|collider| {
+            spawn_body(&mut app, Vec2::new(20.0, 20.0), Vec2::X * 5000.0, collider)
+        });
+        for _ in 0..10 {
+            app.update();
+        }
+        for body in bodies {
+            let position = position(&app, body);
+            assert!(position.x <= 29.6 + 1e-3 && position.x > 29.0, "{position}");
+        }
+    }
+
+    #[test]
+    fn bodies_rest_on_flat_ground() {
+        let world = World::filled(60, 40, |_, y| {
+            if y < 10 {
+                WorldTile::Dirt
+            } else {
+                WorldTile::Air
+            }
+        });
+        let mut app = physics_app(world);
+        let body = spawn_body(
+            &mut app,
+            Vec2::new(30.0, 10.5),
+            Vec2::ZERO,
+            Collider::aabb(Vec2::new(1.0, 0.5)),
+        );
+        for _ in 0..8 {
+            app.update();
+        }
+        let start = position(&app, body);
+        for _ in 0..128 {
+            app.update();
+            assert!(app.world.get::<Grounded>(body).unwrap().0);
+            assert!(position(&app, body).distance(start) < 1e-4);
+        }
+        assert!((start.y - 10.5).abs() < 0.01, "{start}");
+        assert!(app.world.get::<Rigidbody>(body).unwrap().velocity.y.abs() < 1.0);
+    }
+
+    #[test]
+    fn round_colliders_land_on_slopes() {
+        // A slope of 45° going down to the right, its surface is the diagonal x + y = 21
+        let slope = || {
+            World::filled(40, 40, |x, y| {
+                if y <= 20 - x {
+                    WorldTile::Dirt
+                } else {
+                    WorldTile::Air
+                }
+            })
+        };
+        let normal = Vec2::ONE.normalize();
+        // The lowest point of the capsule is the center of its bottom half circle
+        for (collider, bottom) in [
+            (Collider::circle(0.5), Vec2::ZERO),
+            (Collider::capsule(0.5, 0.5), Vec2::new(0.0, -0.5)),
+        ] {
+            let mut app = physics_app(slope());
+            let body = spawn_body(&mut app, Vec2::new(10.5, 15.0), Vec2::ZERO, collider);
+            let mut landed = false;
+            for _ in 0..48 {
+                app.update();
+                let distance = (position(&app, body) + bottom).dot(normal) - 21.0 / 2f32.sqrt();
+                assert!(distance > 0.5 - 0.01, "{distance}");
+                if let Some(hit) = app.world.get::<Intersection>(body).unwrap().hit {
+                    assert!(hit.normal.distance(normal) < 0.01, "{}", hit.normal);
+                    landed = true;
+                }
+            }
+            assert!(landed);
+        }
+    }
 }
@@ -0,0 +1,147 @@
+use std::collections::BTreeSet;
+
+use bevy::{prelude::*, utils::HashMap};
+
+use super::{Collider, Rigidbody, WorldTransform};
+use crate::TILE_SIZE;
+
+/// The size of the cells of the broad phase in tiles.
+/// Bodies are only tested against bodies that share at least one cell with them.
+const CELL_SIZE: isize = 16;
+
+type BodyQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static mut Transform,
+        &'static mut WorldTransform,
+        &'static Collider,
+        Option<&'static mut Rigidbody>,
+    ),
+>;
+
+/// Sent when two bodies with a collider intersect and get pushed apart
+#[derive(Event, Debug, Clone, Copy)]
+pub struct BodyCollision {
+    pub entities: [Entity; 2],
+    /// The unit vector that points from the first body to the second body
+    pub normal: Vec2,
+    /// How far the bodies intersected along the normal, in tiles
+    pub depth: f32,
+}
+
+/// This system pushes apart bodies that intersect each other and lets them bounce off each other.
+///
+/// How far each body moves depends on its mass. Colliders without a [`Rigidbody`] don't move at all.
+pub(super) fn collide_bodies(mut bodies: BodyQuery, mut collisions: EventWriter<BodyCollision>) {
+    for [entity_a, entity_b] in find_candidate_pairs(&bodies) {
+        let Ok(
+            [(_, mut transform_a, mut world_transform_a, collider_a, mut body_a), (_, mut transform_b, mut world_transform_b, collider_b, mut body_b)],
+        ) = bodies.get_many_mut([entity_a, entity_b])
+        else {
+            continue;
+        };
+        // Bodies are tested against each other by the bounds of their colliders
+        let rect_a = collider_a.at(world_transform_a.translation).bounds();
+        let rect_b = collider_b.at(world_transform_b.translation).bounds();
+        let Some((normal, depth)) = aabb_intersection(rect_a, rect_b) else {
+            continue;
+        };
+
+        let inverse_mass_a = body_a.as_ref().map_or(0.0, |body| body.mass.recip());
+        let inverse_mass_b = body_b.as_ref().map_or(0.0, |body| body.mass.recip());
+        let total_inverse_mass = inverse_mass_a + inverse_mass_b;
+        if total_inverse_mass > 0.0 {
+            let correction = normal * depth / total_inverse_mass;
+            move_body(
+                &mut transform_a,
+                &mut world_transform_a,
+                -correction * inverse_mass_a,
+            );
+            move_body(
+                &mut transform_b,
+                &mut world_transform_b,
+                correction * inverse_mass_b,
+            );
+
+            let velocity_a = body_a.as_ref().map_or(Vec2::ZERO, |body| body.velocity);
+            let velocity_b = body_b.as_ref().map_or(Vec2::ZERO, |body| body.velocity);
+            let approach_speed = (velocity_a - velocity_b).dot(normal);
+            // Only bodies that move towards each other bounce off
+            if approach_speed > 0.0 {
+                let restitution = f32::min(
+                    body_a.as_ref().map_or(0.0, |body| body.restitution),
+                    body_b.as_ref().map_or(0.0, |body| body.restitution),
+                );
+                let impulse = normal * approach_speed * (1.0 + restitution) / total_inverse_mass;
+                if let Some(body) = &mut body_a {
+                    body.apply_impulse(-impulse);
+                }
+                if let Some(body) = &mut body_b {
+                    body.apply_impulse(impulse);
+                }
+            }
+        }
+
+        collisions.send(BodyCollision {
+            entities: [entity_a, entity_b],
+            normal,
+            depth,
+        });
+    }
+}
+
+/// Returns all pairs of bodies that share a cell of the broad phase.
+/// The pairs are sorted, so that they are always resolved in the same order.
+fn find_candidate_pairs(bodies: &BodyQuery) -> BTreeSet<[Entity; 2]> {
+    let mut cells: HashMap<(isize, isize), Vec<Entity>> = HashMap::new();
+    for (entity, _, world_transform, collider, _) in bodies.iter() {
+        let (tile_x, tile_y) = world_transform.tile_position;
+        // The bounds of the collider relative to the tile of the body
+        let bounds = collider.at(Vec2::ZERO).bounds();
+        let (min, max) = (bounds.min.floor(), bounds.max.ceil());
+        let cells_x = (tile_x + min.x as isize).div_euclid(CELL_SIZE)
+            ..=(tile_x + max.x as isize).div_euclid(CELL_SIZE);
+        for cell_x in cells_x {
+            let cells_y = (tile_y + min.y as isize).div_euclid(CELL_SIZE)
+                ..=(tile_y + max.y as isize).div_euclid(CELL_SIZE);
+            for cell_y in cells_y {
+                cells.entry((cell_x, cell_y)).or_default().push(entity);
+            }
+        }
+    }
+
+    let mut pairs = BTreeSet::new();
+    for entities in cells.values() {
+        for (index, a) in entities.iter().enumerate() {
+            for b in &entities[index + 1..] {
+                // Bodies that share multiple cells should only be tested once
+                pairs.insert(if a < b { [*a, *b] } else { [*b, *a] });
+            }
+        }
+    }
+    pairs
+}
+
+/// Returns the unit vector from `a` to `b` along the axis with the smallest overlap, and the overlap
+fn aabb_intersection(a: Rect, b: Rect) -> Option<(Vec2, f32)> {
+    let overlap = a.intersect(b);
+    if overlap.is_empty() {
+        return None;
+    }
+    let size = overlap.size();
+    let direction = b.center() - a.center();
+    if size.x < size.y {
+        let normal = Vec2::new(if direction.x < 0.0 { -1.0 } else { 1.0 }, 0.0);
+        Some((normal, size.x))
+    } else {
+        let normal = Vec2::new(0.0, if direction.y < 0.0 { -1.0 } else { 1.0 });
+        Some((normal, size.y))
+    }
+}
+
+fn move_body(transform: &mut Transform, world_transform: &mut WorldTransform, offset: Vec2) {
+    transform.translation += (offset * TILE_SIZE).extend(0.0);
+    *world_transform = WorldTransform::from_translation(world_transform.translation + offset);
+}
@@ -0,0 +1,213 @@
+use bevy::prelude::*;
+
+/// Entities with this component and an [`super::Intersection`] and [`super::WorldTransform`]
+/// component will have collision detection
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Collider {
+    pub shape: ColliderShape,
+    /// The offset of the center of the shape from the position of the entity, in tiles
+    pub offset: Vec2,
+    /// The counterclockwise rotation of the shape around the position of the entity, in radians
+    pub rotation: f32,
+}
+
+/// The shape of a [`Collider`], with all sizes in tiles
+#[derive(Debug, Clone, Copy)]
+pub enum ColliderShape {
+    /// An axis aligned box
+    Aabb {
+        half_extents: Vec2,
+    },
+    Circle {
+        radius: f32,
+    },
+    /// A vertical box with a half circle on top and at the bottom.
+    /// `half_height` is the half height of the box, without the half circles.
+    Capsule {
+        half_height: f32,
+        radius: f32,
+    },
+}
+
+impl Collider {
+    pub fn aabb(half_extents: Vec2) -> Self {
+        Self::from_shape(ColliderShape::Aabb { half_extents })
+    }
+
+    pub fn circle(radius: f32) -> Self {
+        Self::from_shape(ColliderShape::Circle { radius })
+    }
+
+    pub fn capsule(half_height: f32, radius: f32) -> Self {
+        Self::from_shape(ColliderShape::Capsule {
+            half_height,
+            radius,
+        })
+    }
+
+    fn from_shape(shape: ColliderShape) -> Self {
+        Self {
+            shape,
+            offset: Vec2::ZERO,
+            rotation: 0.0,
+        }
+    }
+
+    pub fn with_offset(self, offset: Vec2) -> Self {
+        Self { offset, ..self }
+    }
+
+    /// Returns the shape of this collider for an entity at the given position in tiles
+    pub fn at(&self, position: Vec2) -> RoundedRect {
+        let rotation = Vec2::from_angle(self.rotation);
+        let (half_extents, radius) = match self.shape {
+            ColliderShape::Aabb { half_extents } => (half_extents, 0.0),
+            ColliderShape::Circle { radius } => (Vec2::ZERO, radius),
+            ColliderShape::Capsule {
+                half_height,
+                radius,
+            } => (Vec2::new(0.0, half_height), radius),
+        };
+        RoundedRect {
+            center: position + rotation.rotate(self.offset),
+            half_extents,
+            radius,
+            rotation,
+        }
+    }
+}
+
+/// A rect that is grown by a radius in every direction, which rounds its corners.
+///
+/// All collider shapes can be described like this: boxes have a radius of zero,
+/// circles have no extents and capsules only have a height.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RoundedRect {
+    pub center: Vec2,
+    /// The half size of the rect before it is grown by the radius
+    pub half_extents: Vec2,
+    pub radius: f32,
+    /// The rotation around the center as a unit vector, which is (1, 0) for axis aligned rects
+    pub rotation: Vec2,
+}
+
+impl RoundedRect {
+    pub fn translated(self, offset: Vec2) -> Self {
+        Self {
+            center: self.center + offset,
+            ..self
+        }
+    }
+
+    /// The smallest axis aligned rect that contains this shape
+    pub fn bounds(self) -> Rect {
+        let (cos, sin) = (self.rotation.x.abs(), self.rotation.y.abs());
+        let half_size = Vec2::new(
+            self.half_extents.x * cos + self.half_extents.y * sin,
+            self.half_extents.x * sin + self.half_extents.y * cos,
+        );
+        Rect::from_center_half_size(self.center, half_size + self.radius)
+    }
+
+    /// The distance from the center to the furthest point of the shape along the unit vector
+    pub fn extent(self, direction: Vec2) -> f32 {
+        self.half_extents.dot(self.unrotate(direction).abs()) + self.radius
+    }
+
+    /// Returns the point of the shape that reaches the furthest along the unit vector
+    pub fn support_point(self, direction: Vec2) -> Vec2 {
+        let local_direction = self.unrotate(direction);
+        let corner = Vec2::select(
+            local_direction.cmpeq(Vec2::ZERO),
+            Vec2::ZERO,
+            self.half_extents * local_direction.signum(),
+        );
+        self.center + self.rotation.rotate(corner) + direction * self.radius
+    }
+
+    /// The unit vectors along the sides of the rect
+    pub fn axes(self) -> [Vec2; 2] {
+        [self.rotation, self.rotation.perp()]
+    }
+
+    /// Returns whether the line segment overlaps the shape on both of the axes of the shape.
+    /// Segments that don't are completely on one side of the shape.
+    pub fn overlaps_segment_bounds(self, start: Vec2, end: Vec2) -> bool {
+        let segment = Rect::from_corners(self.to_local(start), self.to_local(end));
+        let half_size = self.half_extents + self.radius;
+        segment.min.x < half_size.x
+            && segment.max.x > -half_size.x
+            && segment.min.y < half_size.y
+            && segment.max.y > -half_size.y
+    }
+
+    /// Returns whether the line segment intersects this shape, touching doesn't count
+    pub fn intersects_segment(self, start: Vec2, end: Vec2) -> bool {
+        let (start, end) = (self.to_local(start), self.to_local(end));
+        let rect = Rect::from_center_half_size(Vec2::ZERO, self.half_extents);
+        segment_rect_distance(start, end, rect) < self.radius
+            || (self.radius == 0.0 && segment_crosses_rect(start, end, rect))
+    }
+
+    /// Transforms a point into the coordinates of the unrotated shape, with the center at zero
+    fn to_local(self, point: Vec2) -> Vec2 {
+        self.unrotate(point - self.center)
+    }
+
+    /// Undoes the rotation of the shape for the vector
+    fn unrotate(self, vector: Vec2) -> Vec2 {
+        Vec2::new(self.rotation.x, -self.rotation.y).rotate(vector)
+    }
+}
+
+/// Returns whether the segment passes through the inside of the rect
+fn segment_crosses_rect(start: Vec2, end: Vec2, rect: Rect) -> bool {
+    let bounds = Rect::from_corners(start, end);
+    if bounds.min.x >= rect.max.x
+        || bounds.max.x <= rect.min.x
+        || bounds.min.y >= rect.max.y
+        || bounds.max.y <= rect.min.y
+    {
+        return false;
+    }
+    // The corners of the rect have to be on different sides of the line
+    let normal = (end - start).perp();
+    let sides = [
+        rect.min,
+        Vec2::new(rect.min.x, rect.max.y),
+        rect.max,
+        Vec2::new(rect.max.x, rect.min.y),
+    ]
+    .map(|corner| (corner - start).dot(normal));
+    sides.iter().any(|side| *side > 0.0) && sides.iter().any(|side| *side < 0.0)
+}
+
+/// Returns the shortest distance between the segment and the rect, or 0 if they intersect
+fn segment_rect_distance(start: Vec2, end: Vec2, rect: Rect) -> f32 {
+    if rect.contains(start) || rect.contains(end) || segment_crosses_rect(start, end, rect) {
+        return 0.0;
+    }
+    // Otherwise the closest points are an end of the segment or a corner of the rect
+    let corners = [
+        rect.min,
+        Vec2::new(rect.min.x, rect.max.y),
+        rect.max,
+        Vec2::new(rect.max.x, rect.min.y),
+    ];
+    let end_distances = [start, end].map(|point| point.distance(point.clamp(rect.min, rect.max)));
+    let corner_distances = corners.map(|corner| point_segment_distance(corner, start, end));
+    end_distances
+        .into_iter()
+        .chain(corner_distances)
+        .fold(f32::INFINITY, f32::min)
+}
+
+fn point_segment_distance(point: Vec2, start: Vec2, end: Vec2) -> f32 {
+    let direction = end - start;
+    let t = if direction == Vec2::ZERO {
+        0.0
+    } else {
+        ((point - start).dot(direction) / direction.length_squared()).clamp(0.0, 1.0)
+    };
+    point.distance(start + direction * t)
+}
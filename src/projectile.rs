@@ -0,0 +1,232 @@
+use bevy::prelude::*;
+
+use crate::{
+    controls::FireCommand,
+    physics::{collider::Collider, PhysicsStep, Rigidbody},
+    tank::{
+        turret::{apply_aim_commands, Turret},
+        Tank,
+    },
+    world::explosion::ExplosionReport,
+    GameWorld, TILE_SIZE,
+};
+
+/// Lets tanks fire shells that fly through the world and explode on impact
+pub struct ProjectilePlugin;
+
+impl Plugin for ProjectilePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Wind>()
+            .add_event::<Explosion>()
+            .add_systems(
+                FixedUpdate,
+                // Shells leave the barrel in the direction that it is aimed at during this step
+                (fire_turrets.after(apply_aim_commands), apply_wind),
+            )
+            .add_systems(FixedPostUpdate, detect_impacts.after(PhysicsStep));
+    }
+}
+
+/// The radius of shells in tiles
+const SHELL_RADIUS: f32 = 0.25;
+const SHELL_COLOR: Color = Color::rgb(0.2, 0.2, 0.2);
+/// The power of the explosion of a shell, which is about the radius of the crater in dirt
+const SHELL_EXPLOSION_POWER: f32 = 4.0;
+
+/// The acceleration in tiles per second² that pushes all projectiles in addition to gravity
+#[derive(Resource, Debug, Default, Clone, Copy, Deref, DerefMut)]
+pub struct Wind(pub Vec2);
+
+/// A shell that explodes when it hits the terrain or a tank
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Projectile {
+    /// The power of the explosion, see [`crate::world::World::explode`]
+    pub explosion_power: f32,
+}
+
+/// Sent when a projectile explodes
+#[derive(Event, Debug, Clone)]
+pub struct Explosion {
+    /// The center of the explosion in tiles
+    pub position: Vec2,
+    pub power: f32,
+    /// The tank that was hit directly, if the projectile didn't hit the terrain first
+    pub tank: Option<Entity>,
+    /// What the explosion did to the world
+    pub report: ExplosionReport,
+}
+
+#[derive(Bundle)]
+struct ProjectileBundle {
+    projectile: Projectile,
+    body: Rigidbody,
+    sprite_bundle: SpriteBundle,
+}
+
+impl ProjectileBundle {
+    /// Creates a shell at the given position in tiles
+    fn shell(position: Vec2, velocity: Vec2, wind: Vec2) -> Self {
+        Self {
+            projectile: Projectile {
+                explosion_power: SHELL_EXPLOSION_POWER,
+            },
+            body: Rigidbody {
+                velocity,
+                acceleration: wind,
+                ..default()
+            },
+            sprite_bundle: SpriteBundle {
+                sprite: Sprite {
+                    color: SHELL_COLOR,
+                    custom_size: Some(Vec2::splat(SHELL_RADIUS * 2.0 * TILE_SIZE)),
+                    ..default()
+                },
+                // Shells are drawn in front of the tanks
+                transform: Transform::from_translation((position * TILE_SIZE).extend(1.0)),
+                ..default()
+            },
+        }
+    }
+}
+
+/// This system spawns a shell at the end of the barrels of all tanks that fire during this step
+fn fire_turrets(
+    mut commands: Commands,
+    wind: Res<Wind>,
+    tanks: Query<(&FireCommand, &Transform, &Children), With<Tank>>,
+    turrets: Query<(&Turret, &Transform)>,
+) {
+    for (fire, tank_transform, children) in tanks.iter() {
+        if !fire.0 {
+            continue;
+        }
+        for (turret, turret_transform) in turrets.iter_many(children) {
+            // The global transforms are only updated after the physics moved the tank
+            let transform = GlobalTransform::from(*tank_transform).mul_transform(*turret_transform);
+            let (position, velocity) = turret.muzzle(&transform);
+            commands.spawn(ProjectileBundle::shell(position, velocity, wind.0));
+        }
+    }
+}
+
+fn apply_wind(wind: Res<Wind>, mut projectiles: Query<&mut Rigidbody, With<Projectile>>) {
+    for mut body in projectiles.iter_mut() {
+        body.acceleration = wind.0;
+    }
+}
+
+/// This system lets projectiles explode when they hit the terrain or a tank during the last step,
+/// and removes projectiles that left the world.
+///
+/// Projectiles don't have a collider, so the path that they moved along is tested instead.
+/// This way, fast projectiles can't pass through thin terrain or tanks between two steps.
+/// The path is tested against the shapes of the tiles, so shells explode where bodies collide with the terrain.
+fn detect_impacts(
+    mut commands: Commands,
+    time: Res<Time<Fixed>>,
+    mut world: ResMut<GameWorld>,
+    mut explosions: EventWriter<Explosion>,
+    projectiles: Query<(Entity, &Projectile, &Transform, &Rigidbody)>,
+    tanks: Query<(Entity, &Transform, &Collider), With<Tank>>,
+) {
+    let delta = time.timestep().as_secs_f32();
+    for (entity, projectile, transform, body) in projectiles.iter() {
+        let end = transform.translation.xy() / TILE_SIZE;
+        let start = end - body.velocity * delta;
+
+        let motion = end - start;
+        let terrain_hit = world.raycast(start, motion, motion.length());
+        let impact_position = terrain_hit.map_or(end, |hit| hit.point);
+        // Only tanks before the terrain can be hit
+        let tank = tanks
+            .iter()
+            .find(|(_, tank_transform, collider)| {
+                let mut shape = collider.at(tank_transform.translation.xy() / TILE_SIZE);
+                shape.radius += SHELL_RADIUS;
+                shape.intersects_segment(start, impact_position)
+            })
+            .map(|(tank, ..)| tank);
+
+        if terrain_hit.is_some() || tank.is_some() {
+            let report = world.explode(
+                impact_position.x.floor() as isize,
+                impact_position.y.floor() as isize,
+                projectile.explosion_power,
+            );
+            explosions.send(Explosion {
+                position: impact_position,
+                power: projectile.explosion_power,
+                tank,
+                report,
+            });
+            commands.entity(entity).despawn();
+        } else if end.x < 0.0 || end.x >= world.width as f32 || end.y < 0.0 {
+            // Projectiles above the world fall back down, but the others never come back
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+    use crate::{
+        physics::PhysicsPlugin,
+        world::{World, WorldTile},
+    };
+
+    #[derive(Resource, Default)]
+    struct Explosions(Vec<Explosion>);
+
+    fn record_explosions(mut events: EventReader<Explosion>, mut explosions: ResMut<Explosions>) {
+        explosions.0.extend(events.read().cloned());
+    }
+
+    #[test]
+    fn shells_explode_on_the_surface_of_slopes() {
+        // A slope of 45° going down to the right, so the top tiles are triangles
+        let mut world = World::filled(40, 40, |x, y| {
+            if y <= 20 - x {
+                WorldTile::Dirt
+            } else {
+                WorldTile::Air
+            }
+        });
+        world.settle();
+
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<Shader>()
+            .add_plugins((
+                bevy::gizmos::GizmoPlugin,
+                PhysicsPlugin { tick_rate: 64.0 },
+                ProjectilePlugin,
+            ))
+            .insert_resource(GameWorld(world))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+                1.0 / 64.0,
+            )))
+            .init_resource::<Explosions>()
+            .add_systems(Update, record_explosions);
+        // Dropped into the empty upper right corner of the tile at (5, 15)
+        app.world.spawn(ProjectileBundle::shell(
+            Vec2::new(5.75, 30.0),
+            Vec2::ZERO,
+            Vec2::ZERO,
+        ));
+        for _ in 0..128 {
+            app.update();
+        }
+
+        let explosions = &app.world.resource::<Explosions>().0;
+        assert_eq!(explosions.len(), 1);
+        let position = explosions[0].position;
+        // The surface of the slope is the diagonal x + y = 21
+        assert!((position.x + position.y - 21.0).abs() < 1e-3, "{position}");
+        assert!(explosions[0].report.total_destroyed() > 0);
+    }
+}
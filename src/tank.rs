@@ -1,15 +1,28 @@
+use std::f32::consts::FRAC_PI_4;
+
 use bevy::prelude::*;
 
 use crate::{
-    physics::{Collider, Intersection, Rigidbody, WorldTransform},
-    TILE_SIZE,
+    controls::{apply_move_commands, AimCommand, FireCommand, MoveCommand},
+    physics::{
+        collider::{Collider, RoundedRect},
+        Grounded, Intersection, Rigidbody, WorldTransform,
+    },
+    world::World,
+    GameWorld, TILE_SIZE,
 };
 
+use self::turret::TurretPlugin;
+
+pub mod turret;
+
 pub struct TankPlugin;
 
 impl Plugin for TankPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, (render_tank, rotate_tank_texture))
+        app.add_plugins(TurretPlugin)
+            .add_systems(FixedUpdate, tilt_tanks.after(apply_move_commands))
+            .add_systems(Update, rotate_tank_texture)
             .add_systems(PostUpdate, add_texture_to_tanks);
     }
 }
@@ -17,6 +30,20 @@ impl Plugin for TankPlugin {
 #[derive(Component, Debug)]
 pub struct Tank;
 
+/// The width and height of the tank sprite in tiles
+const TANK_SIZE: f32 = 8.0;
+
+/// Tanks can't drive up slopes that are steeper than this angle in radians.
+/// This is also the steepest slope that they can stand on without sliding down.
+const MAX_CLIMB_ANGLE: f32 = FRAC_PI_4;
+/// How fast tanks tilt towards the slope of the terrain, in radians per second
+const TILT_SPEED: f32 = 3.0;
+/// How far below the bottom of the hull the terrain is still considered to be under the tracks, in tiles
+const GROUND_SAMPLE_DEPTH: f32 = 2.0;
+/// How far inside of the hull the terrain is sampled from, in tiles.
+/// This finds the terrain even when the hull sinks into it slightly.
+const GROUND_SAMPLE_INSET: f32 = 0.5;
+
 #[derive(Bundle)]
 pub struct TankBundle {
     pub tank: Tank,
@@ -25,6 +52,10 @@ pub struct TankBundle {
     pub rigidbody: Rigidbody,
     pub collider: Collider,
     pub intersection: Intersection,
+    pub grounded: Grounded,
+    pub command: MoveCommand,
+    pub fire_command: FireCommand,
+    pub aim_command: AimCommand,
 }
 
 impl Default for TankBundle {
@@ -33,31 +64,80 @@ impl Default for TankBundle {
             tank: Tank,
             spatial_bundle: default(),
             world_transform: default(),
-            rigidbody: default(),
-            collider: Collider,
+            rigidbody: Rigidbody {
+                // Tanks can stand on slopes of 45° without sliding down
+                friction: 1.0,
+                ..default()
+            },
+            // The hull only covers the bottom half of the sprite
+            collider: Collider::aabb(Vec2::new(3.5, 2.0)).with_offset(Vec2::new(0.0, -2.0)),
             intersection: default(),
+            grounded: default(),
+            command: default(),
+            fire_command: default(),
+            aim_command: default(),
         }
     }
 }
 
-fn render_tank(mut gizmos: Gizmos, query: Query<&Transform, With<Tank>>) {
-    for transform in query.iter() {
-        gizmos.rect_2d(
-            transform.translation.xy(),
-            0.0,
-            transform.scale.xy() * TILE_SIZE,
-            Color::WHITE,
-        );
+/// This system tilts tanks to match the slope of the terrain under their tracks
+/// and stops them from driving up slopes that are too steep.
+fn tilt_tanks(
+    time: Res<Time<Fixed>>,
+    world: Res<GameWorld>,
+    mut tanks: Query<(&mut Transform, &mut Collider, &mut Rigidbody), With<Tank>>,
+) {
+    for (mut transform, mut collider, mut body) in tanks.iter_mut() {
+        let hull = collider.at(transform.translation.xy() / TILE_SIZE);
+        let slope = terrain_slope(&world, hull);
+
+        // Tanks level out while they are in the air
+        let max_step = TILT_SPEED * time.timestep().as_secs_f32();
+        collider.rotation += (slope.unwrap_or(0.0) - collider.rotation).clamp(-max_step, max_step);
+        transform.rotation = Quat::from_rotation_z(collider.rotation);
+
+        if let Some(slope) = slope {
+            let uphill = body.acceleration.x * slope > 0.0;
+            if uphill && slope.abs() > MAX_CLIMB_ANGLE {
+                body.acceleration.x = 0.0;
+            }
+        }
+    }
+}
+
+/// Returns the angle of the terrain under the tracks,
+/// or `None` if there is no terrain close below either end of the hull.
+///
+/// The terrain is sampled below both ends of the bottom of the hull. If only one end is above the terrain,
+/// like when landing on a slope with a level hull, the slope of the terrain below that end is used.
+fn terrain_slope(world: &World, hull: RoundedRect) -> Option<f32> {
+    let bottom = -hull.half_extents.y - hull.radius + GROUND_SAMPLE_INSET;
+    let [left, right] = [-hull.half_extents.x, hull.half_extents.x].map(|x| {
+        let origin = hull.center + hull.rotation.rotate(Vec2::new(x, bottom));
+        world.raycast(
+            origin,
+            Vec2::NEG_Y,
+            GROUND_SAMPLE_INSET + GROUND_SAMPLE_DEPTH,
+        )
+    });
+    match (left, right) {
+        (Some(left), Some(right)) => {
+            let difference = right.point - left.point;
+            Some(difference.y.atan2(difference.x))
+        }
+        (Some(hit), None) | (None, Some(hit)) => Some((-hit.normal.x).atan2(hit.normal.y)),
+        (None, None) => None,
     }
 }
 
+#[allow(clippy::type_complexity)]
 fn rotate_tank_texture(
     mut query: Query<(&mut Sprite, &Rigidbody), (With<Tank>, Changed<Rigidbody>)>,
 ) {
     for (mut sprite, body) in query.iter_mut() {
-        if body.motion.x < 0.0 && !sprite.flip_x {
+        if body.acceleration.x < 0.0 && !sprite.flip_x {
             sprite.flip_x = true;
-        } else if body.motion.x > 0.0 && sprite.flip_x {
+        } else if body.acceleration.x > 0.0 && sprite.flip_x {
             sprite.flip_x = false;
         }
     }
@@ -72,7 +152,7 @@ fn add_texture_to_tanks(
         commands.entity(entity).insert((
             asset_server.load::<Image>("textures/tank.png"),
             Sprite {
-                custom_size: Some(Vec2::splat(TILE_SIZE)),
+                custom_size: Some(Vec2::splat(TANK_SIZE * TILE_SIZE)),
                 ..default()
             },
         ));
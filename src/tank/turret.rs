@@ -0,0 +1,273 @@
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+use bevy::{prelude::*, sprite::Anchor};
+
+use super::{tilt_tanks, Tank};
+use crate::{
+    controls::{AimCommand, Player},
+    physics::Gravity,
+    projectile::Wind,
+    GameWorld, TILE_SIZE,
+};
+
+pub struct TurretPlugin;
+
+impl Plugin for TurretPlugin {
+    fn build(&self, app: &mut App) {
+        // The hull has to be tilted before aiming relative to it
+        app.add_systems(FixedUpdate, apply_aim_commands.after(tilt_tanks))
+            .add_systems(Update, show_trajectory)
+            .add_systems(PostUpdate, (add_turrets_to_tanks, update_turret_transforms));
+    }
+}
+
+/// Where the turret is mounted on the tank, relative to the center of the tank in tiles.
+/// This is the center of the dome in the tank texture, which is mirrored when the texture is flipped.
+const TURRET_OFFSET: Vec2 = Vec2::new(-0.375, -1.375);
+/// The size of the barrel in tiles, starting at the center of the dome.
+/// The barrel reaches past the hull, so that shells don't hit the tank that fires them.
+const BARREL_SIZE: Vec2 = Vec2::new(4.5, 0.5);
+/// The color of the dome in the tank texture
+const BARREL_COLOR: Color = Color::rgb(0.384, 0.4, 0.384);
+
+/// The speed of shells in tiles per second
+const MIN_POWER: f32 = 10.0;
+const MAX_POWER: f32 = 80.0;
+
+/// How fast the keyboard turns the turret, in radians per second
+const AIM_SPEED: f32 = 1.5;
+/// How fast the keyboard changes the power, in tiles per second²
+const POWER_SPEED: f32 = 30.0;
+/// The power per tile of distance between the cursor and the turret when aiming with the mouse
+const MOUSE_POWER_PER_TILE: f32 = 1.5;
+
+/// How far ahead the trajectory is shown, in seconds
+const TRAJECTORY_DURATION: f32 = 4.0;
+
+/// The gun of a tank, which is a child entity of the tank
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Turret {
+    /// The counterclockwise angle of the barrel relative to the hull, in radians.
+    /// 0 points to the right of the hull and π to the left.
+    pub angle: f32,
+    /// The speed that shells leave the barrel with, in tiles per second
+    pub power: f32,
+}
+
+impl Default for Turret {
+    fn default() -> Self {
+        Self {
+            angle: FRAC_PI_4,
+            power: 30.0,
+        }
+    }
+}
+
+impl Turret {
+    fn set_angle(&mut self, angle: f32) {
+        self.angle = angle.clamp(0.0, PI);
+    }
+
+    fn set_power(&mut self, power: f32) {
+        self.power = power.clamp(MIN_POWER, MAX_POWER);
+    }
+
+    /// Returns the position of the end of the barrel and the velocity of a shell that leaves it,
+    /// in tiles and tiles per second, given the transform of the turret
+    pub fn muzzle(&self, transform: &GlobalTransform) -> (Vec2, Vec2) {
+        let position = transform.translation().xy() / TILE_SIZE;
+        let end = transform
+            .transform_point(Vec3::X * BARREL_SIZE.x * TILE_SIZE)
+            .xy()
+            / TILE_SIZE;
+        let direction = (end - position).normalize_or_zero();
+        (end, direction * self.power)
+    }
+}
+
+fn add_turrets_to_tanks(mut commands: Commands, tanks: Query<Entity, Added<Tank>>) {
+    for tank in tanks.iter() {
+        let turret = Turret::default();
+        commands.entity(tank).with_children(|parent| {
+            parent.spawn((
+                turret,
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: BARREL_COLOR,
+                        custom_size: Some(BARREL_SIZE * TILE_SIZE),
+                        // The barrel turns around its end at the tank
+                        anchor: Anchor::CenterLeft,
+                        ..default()
+                    },
+                    // The barrel is drawn behind the tank
+                    transform: Transform::from_translation(
+                        (TURRET_OFFSET * TILE_SIZE).extend(-0.1),
+                    )
+                    .with_rotation(Quat::from_rotation_z(turret.angle)),
+                    ..default()
+                },
+            ));
+        });
+    }
+}
+
+/// This system turns the barrels and keeps them on the dome when the tank texture is flipped
+fn update_turret_transforms(
+    mut turrets: Query<(&mut Transform, &Turret, &Parent)>,
+    tanks: Query<&Sprite, With<Tank>>,
+) {
+    for (mut transform, turret, parent) in turrets.iter_mut() {
+        let flipped = tanks.get(parent.get()).is_ok_and(|sprite| sprite.flip_x);
+        let x = if flipped {
+            -TURRET_OFFSET.x
+        } else {
+            TURRET_OFFSET.x
+        } * TILE_SIZE;
+        if transform.translation.x != x {
+            transform.translation.x = x;
+        }
+        let rotation = Quat::from_rotation_z(turret.angle);
+        if transform.rotation != rotation {
+            transform.rotation = rotation;
+        }
+    }
+}
+
+/// This system turns the turrets and changes their power as commanded for this physics step
+pub(crate) fn apply_aim_commands(
+    time: Res<Time<Fixed>>,
+    tanks: Query<(&AimCommand, &Transform, &Children), With<Tank>>,
+    mut turrets: Query<(&mut Turret, &Transform)>,
+) {
+    let delta = time.timestep().as_secs_f32();
+    for (command, tank_transform, children) in tanks.iter() {
+        let mut turrets = turrets.iter_many_mut(children);
+        while let Some((mut turret, turret_transform)) = turrets.fetch_next() {
+            let Some(target) = command.target else {
+                let angle = turret.angle + command.turn * AIM_SPEED * delta;
+                turret.set_angle(angle);
+                let power = turret.power + command.power * POWER_SPEED * delta;
+                turret.set_power(power);
+                continue;
+            };
+
+            // The global transforms are only updated after the physics moved the tank
+            let position = tank_transform.transform_point(turret_transform.translation);
+            let offset = target - position.xy() / TILE_SIZE;
+            // There is no direction to aim in when the target is exactly on the turret
+            if offset == Vec2::ZERO {
+                continue;
+            }
+            // The angle is relative to the hull, which can be tilted
+            let hull_direction = (tank_transform.rotation * Vec3::X).xy();
+            let angle = hull_direction.angle_between(offset);
+            // Below the hull, aim at the nearer end of the range
+            turret.set_angle(if angle < -FRAC_PI_2 { PI } else { angle });
+            turret.set_power(offset.length() * MOUSE_POWER_PER_TILE);
+        }
+    }
+}
+
+/// This system shows where shells of the players would fly until they hit the terrain.
+/// The shells are simulated in the same steps as the physics, so they follow the shown path exactly.
+fn show_trajectory(
+    mut gizmos: Gizmos,
+    time: Res<Time<Fixed>>,
+    world: Res<GameWorld>,
+    gravity: Res<Gravity>,
+    wind: Res<Wind>,
+    players: Query<&Children, With<Player>>,
+    turrets: Query<(&Turret, &GlobalTransform)>,
+) {
+    let step = time.timestep().as_secs_f32();
+    for children in players.iter() {
+        for (turret, transform) in turrets.iter_many(children) {
+            let (mut position, mut velocity) = turret.muzzle(transform);
+            let mut points = vec![position * TILE_SIZE];
+            for _ in 0..(TRAJECTORY_DURATION / step) as usize {
+                velocity += (gravity.0 + wind.0) * step;
+                let motion = velocity * step;
+                if let Some(hit) = world.raycast(position, motion, motion.length()) {
+                    points.push(hit.point * TILE_SIZE);
+                    break;
+                }
+                position += motion;
+                points.push(position * TILE_SIZE);
+            }
+            gizmos.linestrip_2d(points, Color::YELLOW);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    /// Spawns a tank at the origin with a turret and returns the turret
+    fn spawn_turret(world: &mut World, command: AimCommand) -> Entity {
+        let turret = world
+            .spawn((
+                Turret::default(),
+                Transform::from_translation((TURRET_OFFSET * TILE_SIZE).extend(0.0)),
+            ))
+            .id();
+        world
+            .spawn((Tank, command, Transform::default()))
+            .push_children(&[turret]);
+        turret
+    }
+
+    #[test]
+    fn aim_commands_change_the_turret_per_step() {
+        let mut world = World::new();
+        world.insert_resource(Time::<Fixed>::from_hz(64.0));
+        let command = AimCommand {
+            turn: 1.0,
+            power: -1.0,
+            target: None,
+        };
+        let turret = spawn_turret(&mut world, command);
+        for _ in 0..32 {
+            world.run_system_once(apply_aim_commands);
+        }
+
+        // Half a second of steps
+        let turret = world.get::<Turret>(turret).unwrap();
+        let default = Turret::default();
+        assert!((turret.angle - (default.angle + AIM_SPEED * 0.5)).abs() < 1e-4);
+        assert!((turret.power - (default.power - POWER_SPEED * 0.5)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn turrets_aim_at_the_target() {
+        let mut world = World::new();
+        world.insert_resource(Time::<Fixed>::from_hz(64.0));
+        // Straight above the turret
+        let target = TURRET_OFFSET + Vec2::Y * 10.0;
+        let above = spawn_turret(
+            &mut world,
+            AimCommand {
+                target: Some(target),
+                ..default()
+            },
+        );
+        // Exactly on the turret, where there is no direction to aim in
+        let on_turret = spawn_turret(
+            &mut world,
+            AimCommand {
+                target: Some(TURRET_OFFSET),
+                ..default()
+            },
+        );
+        world.run_system_once(apply_aim_commands);
+
+        let turret = world.get::<Turret>(above).unwrap();
+        assert!((turret.angle - FRAC_PI_2).abs() < 1e-4, "{}", turret.angle);
+        assert_eq!(turret.power, 10.0 * MOUSE_POWER_PER_TILE);
+        let turret = world.get::<Turret>(on_turret).unwrap();
+        assert_eq!(turret.angle, Turret::default().angle);
+        assert_eq!(turret.power, Turret::default().power);
+    }
+}
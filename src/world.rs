@@ -1,22 +1,163 @@
-use bevy::math::Vec2;
+use bevy::math::{IRect, Vec2};
 use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
 
-use self::world_gen::Wave;
+use self::{
+    chunk::{Chunk, CHUNK_SIZE},
+    islands::IslandSearch,
+};
+
+pub use world_gen::WorldGenConfig;
+
+pub mod chunk;
+pub mod explosion;
+pub mod falling;
+pub mod image_map;
+pub mod islands;
+pub mod map_format;
+pub mod queries;
+pub mod tile_shape;
+pub mod world_gen;
 
 #[derive(Debug)]
 pub struct World {
     pub width: usize,
     pub height: usize,
-    data: Vec<WorldTile>,
+    /// The chunks of this world, row by row, starting at the bottom left.
+    /// Chunks that only contain air are not allocated.
+    chunks: Vec<Option<Box<Chunk>>>,
+    /// The amount of chunks per row
+    chunks_x: usize,
+    /// The positions of all chunks that changed visually since the last call to [`World::take_dirty_chunks`]
+    dirty_chunks: HashSet<(usize, usize)>,
+    /// The regions (inclusive) in which tiles may be able to move, see [`World::simulate_step`].
+    /// Every chunk has its own region, so that modifications far apart don't result in one huge region.
+    unsettled: HashMap<(usize, usize), IRect>,
+    /// The amount of simulation steps performed so far
+    simulation_step: usize,
+    /// Finds islands that were cut off by modifications, see [`World::drop_floating_islands`]
+    island_search: IslandSearch,
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum WorldTile {
     #[default]
     Air,
     Dirt,
+    Stone,
+    Sand,
+    Bedrock,
+    Metal,
+    Water,
+    Scorched,
 }
 
+/// The properties shared by every tile of the same material
+#[derive(Debug, Clone, Copy)]
+pub struct TileProperties {
+    /// The name of the material, which is also used to identify it in map files
+    pub name: &'static str,
+    /// How much an explosion is weakened by this tile, `f32::INFINITY` for tiles that can't be destroyed
+    pub hardness: f32,
+    /// The color used to render this tile
+    pub color: Color,
+    /// Whether physics bodies collide with this tile
+    pub has_collider: bool,
+    /// Whether this tile may be removed or replaced by other tiles
+    pub destructible: bool,
+    /// How this tile moves when it is not supported
+    pub mobility: Mobility,
+}
+
+/// Describes how a tile reacts to gravity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mobility {
+    /// The tile never moves
+    Static,
+    /// The tile falls down and slides off slopes that are steeper than its angle of repose.
+    /// `repose_height` is the amount of free tiles below a diagonal neighbor required for the tile to slide,
+    /// so `1` results in slopes of 45°.
+    Loose { repose_height: u8 },
+    /// The tile falls down and spreads out horizontally
+    Liquid,
+}
+
+/// The material table, indexed by the discriminant of [`WorldTile`]
+const TILE_PROPERTIES: [TileProperties; WorldTile::ALL.len()] = [
+    // Air
+    TileProperties {
+        name: "air",
+        hardness: 0.0,
+        color: Color::NONE,
+        has_collider: false,
+        destructible: true,
+        mobility: Mobility::Static,
+    },
+    // Dirt
+    TileProperties {
+        name: "dirt",
+        hardness: 1.0,
+        color: Color::GOLD,
+        has_collider: true,
+        destructible: true,
+        mobility: Mobility::Loose { repose_height: 2 },
+    },
+    // Stone
+    TileProperties {
+        name: "stone",
+        hardness: 3.0,
+        color: Color::rgb(0.5, 0.5, 0.52),
+        has_collider: true,
+        destructible: true,
+        mobility: Mobility::Static,
+    },
+    // Sand
+    TileProperties {
+        name: "sand",
+        hardness: 0.5,
+        color: Color::rgb(0.94, 0.87, 0.6),
+        has_collider: true,
+        destructible: true,
+        mobility: Mobility::Loose { repose_height: 1 },
+    },
+    // Bedrock
+    TileProperties {
+        name: "bedrock",
+        hardness: f32::INFINITY,
+        color: Color::rgb(0.2, 0.2, 0.22),
+        has_collider: true,
+        destructible: false,
+        mobility: Mobility::Static,
+    },
+    // Metal
+    TileProperties {
+        name: "metal",
+        hardness: 6.0,
+        color: Color::rgb(0.62, 0.68, 0.75),
+        has_collider: true,
+        destructible: true,
+        mobility: Mobility::Static,
+    },
+    // Water
+    TileProperties {
+        name: "water",
+        hardness: 0.0,
+        color: Color::rgba(0.15, 0.4, 0.9, 0.7),
+        has_collider: false,
+        destructible: true,
+        mobility: Mobility::Liquid,
+    },
+    // Scorched
+    TileProperties {
+        name: "scorched",
+        hardness: 1.0,
+        color: Color::rgb(0.35, 0.25, 0.15),
+        has_collider: true,
+        destructible: true,
+        mobility: Mobility::Loose { repose_height: 2 },
+    },
+];
+
 #[derive(Debug, Clone, Copy)]
 pub struct RenderedWorldTile {
     pub pos: (isize, isize),
@@ -26,48 +167,91 @@ pub struct RenderedWorldTile {
 }
 
 impl WorldTile {
+    /// All tile materials, in the order of their discriminants
+    pub const ALL: [Self; 8] = [
+        Self::Air,
+        Self::Dirt,
+        Self::Stone,
+        Self::Sand,
+        Self::Bedrock,
+        Self::Metal,
+        Self::Water,
+        Self::Scorched,
+    ];
+
+    pub fn properties(self) -> &'static TileProperties {
+        &TILE_PROPERTIES[self as usize]
+    }
+
+    pub fn name(self) -> &'static str {
+        self.properties().name
+    }
+
+    /// Returns the material with the given name
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|tile| tile.name() == name)
+    }
+
     pub fn is_not_air(self) -> bool {
         !matches!(self, Self::Air)
     }
 
     pub fn has_collider(self) -> bool {
-        self.is_not_air()
+        self.properties().has_collider
+    }
+
+    pub fn is_destructible(self) -> bool {
+        self.properties().destructible
+    }
+
+    pub fn hardness(self) -> f32 {
+        self.properties().hardness
+    }
+
+    pub fn color(self) -> Color {
+        self.properties().color
+    }
+
+    pub fn mobility(self) -> Mobility {
+        self.properties().mobility
     }
 }
 
 impl World {
     pub fn new(width: usize, height: usize) -> Self {
+        let chunks_x = width.div_ceil(CHUNK_SIZE);
+        let chunks_y = height.div_ceil(CHUNK_SIZE);
         Self {
             width,
             height,
-            data: vec![WorldTile::default(); width * height],
+            chunks: vec![None; chunks_x * chunks_y],
+            chunks_x,
+            dirty_chunks: HashSet::new(),
+            unsettled: HashMap::new(),
+            simulation_step: 0,
+            island_search: IslandSearch::default(),
         }
     }
 
-    pub fn generate(width: usize, height: usize) -> Self {
-        let w = width as f32;
-        let h = height as f32;
-        let worldgen_config = [
-            Wave {
-                height: h * 0.25,
-                speed: std::f32::consts::PI / w,
-                off_y: h * 0.3,
-                off_x: 0.0,
-            },
-            Wave {
-                height: h * 0.1,
-                speed: std::f32::consts::PI / w * 4.0,
-                off_y: 0.0,
-                off_x: std::f32::consts::PI,
-            },
-            Wave {
-                height: h * 0.015,
-                speed: 0.3,
-                off_y: 0.0,
-                off_x: 42.0,
-            },
-        ];
-        world_gen::generate_world(width, height, &worldgen_config)
+    /// Creates a world where every tile is given by `tile_at`
+    #[cfg(test)]
+    pub fn filled(
+        width: usize,
+        height: usize,
+        tile_at: impl Fn(isize, isize) -> WorldTile,
+    ) -> Self {
+        let mut world = Self::new(width, height);
+        for x in 0..width as isize {
+            for y in 0..height as isize {
+                world.set(x, y, tile_at(x, y));
+            }
+        }
+        world
+    }
+
+    /// Generates a new world. The same config always results in the same world.
+    pub fn generate(config: &WorldGenConfig) -> Self {
+        world_gen::generate_world(config)
     }
 
     pub fn fill_radius(
@@ -77,37 +261,116 @@ impl World {
         radius: f32,
         tile: WorldTile,
     ) {
-        let explosion_pos = Vec2::new(explosion_x as f32, explosion_y as f32);
+        self.for_each_in_radius(explosion_x, explosion_y, radius, |world, x, y, _| {
+            if world.get(x, y).is_destructible() {
+                world.set(x, y, tile);
+            }
+        });
+    }
+
+    /// Calls `function` with the position and distance of every tile inside the world
+    /// that is at most `radius` tiles away from the center
+    fn for_each_in_radius(
+        &mut self,
+        center_x: isize,
+        center_y: isize,
+        radius: f32,
+        mut function: impl FnMut(&mut Self, isize, isize, f32),
+    ) {
+        let center_pos = Vec2::new(center_x as f32, center_y as f32);
         let radius_int = (radius + 0.5) as isize;
-        for x in (explosion_x - radius_int)..(explosion_x + radius_int) {
+        for x in (center_x - radius_int)..=(center_x + radius_int) {
             if x < 0 || x >= self.width as isize {
                 continue;
             }
-            for y in (explosion_y - radius_int)..(explosion_y + radius_int) {
+            for y in (center_y - radius_int)..=(center_y + radius_int) {
                 if y < 0 || y >= self.height as isize {
                     continue;
                 }
 
                 let pos = Vec2::new(x as f32, y as f32);
-                let distance = pos.distance(explosion_pos);
+                let distance = pos.distance(center_pos);
                 if distance <= radius {
-                    self.set(x, y, tile);
+                    function(self, x, y, distance);
                 }
             }
         }
     }
 
     pub fn set(&mut self, x: isize, y: isize, tile: WorldTile) {
-        let idx = self.coords_to_index(x, y);
-        self.data[idx] = tile;
+        let (chunk_index, local_x, local_y) = self.coords_to_chunk(x, y);
+        let chunk = &mut self.chunks[chunk_index];
+        let old_tile = chunk
+            .as_ref()
+            .map_or(WorldTile::default(), |chunk| chunk.get(local_x, local_y));
+        if old_tile == tile {
+            return;
+        }
+
+        match chunk {
+            Some(chunk_data) => {
+                chunk_data.set(local_x, local_y, tile);
+                if chunk_data.is_empty() {
+                    *chunk = None;
+                }
+            }
+            None if tile.is_not_air() => {
+                let mut chunk_data = Box::<Chunk>::default();
+                chunk_data.set(local_x, local_y, tile);
+                *chunk = Some(chunk_data);
+            }
+            None => {}
+        }
+        self.mark_unsettled(x, y);
+        self.mark_detachable(x, y, old_tile, tile);
+        self.mark_dirty(x, y);
+    }
+
+    /// Returns the positions of all chunks that have to be rendered again and resets them
+    pub fn take_dirty_chunks(&mut self) -> HashSet<(usize, usize)> {
+        std::mem::take(&mut self.dirty_chunks)
+    }
+
+    /// Marks the chunk containing this tile as dirty.
+    /// Since the rendering of a tile depends on its neighbors, chunks containing a neighbor get marked as well.
+    fn mark_dirty(&mut self, x: isize, y: isize) {
+        for (x, y) in [(x, y), (x, y + 1), (x - 1, y), (x, y - 1), (x + 1, y)] {
+            if self.contains(x, y) {
+                self.dirty_chunks
+                    .insert((x as usize / CHUNK_SIZE, y as usize / CHUNK_SIZE));
+            }
+        }
+    }
+
+    /// Returns the tile coordinates covered by the chunk at the given position,
+    /// as ranges in x and y direction
+    pub fn chunk_bounds(
+        &self,
+        (chunk_x, chunk_y): (usize, usize),
+    ) -> (std::ops::Range<usize>, std::ops::Range<usize>) {
+        let x_range = (chunk_x * CHUNK_SIZE)..((chunk_x + 1) * CHUNK_SIZE).min(self.width);
+        let y_range = (chunk_y * CHUNK_SIZE)..((chunk_y + 1) * CHUNK_SIZE).min(self.height);
+        (x_range, y_range)
     }
 
     pub fn get(&self, x: isize, y: isize) -> WorldTile {
-        if x < 0 || x >= self.width as isize || y < 0 || y >= self.height as isize {
+        if !self.contains(x, y) {
             return WorldTile::default();
         }
-        let idx = self.coords_to_index(x, y);
-        self.data[idx]
+        let (chunk_index, local_x, local_y) = self.coords_to_chunk(x, y);
+        match &self.chunks[chunk_index] {
+            Some(chunk) => chunk.get(local_x, local_y),
+            None => WorldTile::default(),
+        }
+    }
+
+    /// Returns the y coordinate above the highest tile with a collider in the given column,
+    /// or 0 if the column has no such tile
+    pub fn surface_height(&self, x: isize) -> isize {
+        (0..self.height as isize)
+            .rev()
+            .find(|y| self.get(x, *y).has_collider())
+            .map_or(0, |y| y + 1)
     }
 
     pub fn get_rendered(&self, x: isize, y: isize) -> RenderedWorldTile {
@@ -122,10 +385,12 @@ impl World {
     }
 
     pub fn get_rendered_in_rect(&self, rect: Rect) -> impl Iterator<Item = RenderedWorldTile> + '_ {
-        let left_x = (rect.min.x - 1.0) as isize;
-        let bottom_y = (rect.min.y - 1.0) as isize;
-        let width = (rect.width() + 2.0) as isize;
-        let height = (rect.height() + 2.0) as isize;
+        // Include a margin of one tile around every tile that the rect touches
+        let min = rect.min.floor() - 1.0;
+        let max = rect.max.floor() + 1.0;
+        let (left_x, bottom_y) = (min.x as isize, min.y as isize);
+        let width = (max.x - min.x) as isize + 1;
+        let height = (max.y - min.y) as isize + 1;
         self._get_rendered_in_rect(left_x, bottom_y, width, height)
     }
 
@@ -147,40 +412,17 @@ impl World {
         })
     }
 
-    fn coords_to_index(&self, x: isize, y: isize) -> usize {
-        assert!(x < self.width as isize && x >= 0);
-        assert!(y < self.height as isize && y >= 0);
-        let idx = x + y * self.width as isize;
-        idx as usize
-    }
-}
-
-mod world_gen {
-    use super::World;
-
-    #[derive(Debug, Clone, Copy)]
-    pub(super) struct Wave {
-        pub height: f32,
-        pub speed: f32,
-        pub off_y: f32,
-        pub off_x: f32,
-    }
-
-    impl Wave {
-        fn at_x(&self, x: f32) -> f32 {
-            self.off_y + ((x + self.off_x) * self.speed).sin() * self.height
-        }
+    /// Returns whether the given coordinates are inside of the world
+    pub fn contains(&self, x: isize, y: isize) -> bool {
+        x >= 0 && x < self.width as isize && y >= 0 && y < self.height as isize
     }
 
-    pub(super) fn generate_world(width: usize, height: usize, waves: &[Wave]) -> World {
-        let mut world = World::new(width, height);
-        for x in 0..world.width {
-            let height: f32 = waves.iter().map(|wave| wave.at_x(x as f32)).sum();
-            for y in 0..(usize::min(world.height, height as usize)) {
-                world.set(x as isize, y as isize, super::WorldTile::Dirt);
-            }
-        }
-
-        world
+    /// Returns the index of the chunk containing the given tile and the position of the tile inside that chunk
+    fn coords_to_chunk(&self, x: isize, y: isize) -> (usize, usize, usize) {
+        assert!(x < self.width as isize && x >= 0);
+        assert!(y < self.height as isize && y >= 0);
+        let (x, y) = (x as usize, y as usize);
+        let chunk_index = x / CHUNK_SIZE + (y / CHUNK_SIZE) * self.chunks_x;
+        (chunk_index, x % CHUNK_SIZE, y % CHUNK_SIZE)
     }
 }
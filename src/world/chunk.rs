@@ -0,0 +1,49 @@
+use super::WorldTile;
+
+/// The width and height of a chunk in tiles
+pub const CHUNK_SIZE: usize = 32;
+
+/// A square section of the world.
+/// Chunks are only allocated once a non-air tile is placed inside of them.
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    tiles: [WorldTile; CHUNK_SIZE * CHUNK_SIZE],
+    /// The amount of tiles in this chunk that are not air
+    filled: usize,
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Self {
+            tiles: [WorldTile::Air; CHUNK_SIZE * CHUNK_SIZE],
+            filled: 0,
+        }
+    }
+}
+
+impl Chunk {
+    /// Returns the tile at the given position relative to the bottom left corner of this chunk
+    pub fn get(&self, x: usize, y: usize) -> WorldTile {
+        self.tiles[Self::index(x, y)]
+    }
+
+    /// Sets the tile at the given position relative to the bottom left corner of this chunk
+    pub fn set(&mut self, x: usize, y: usize, tile: WorldTile) {
+        let old_tile = std::mem::replace(&mut self.tiles[Self::index(x, y)], tile);
+        match (old_tile.is_not_air(), tile.is_not_air()) {
+            (false, true) => self.filled += 1,
+            (true, false) => self.filled -= 1,
+            _ => {}
+        }
+    }
+
+    /// Returns whether this chunk only contains air
+    pub fn is_empty(&self) -> bool {
+        self.filled == 0
+    }
+
+    fn index(x: usize, y: usize) -> usize {
+        debug_assert!(x < CHUNK_SIZE && y < CHUNK_SIZE);
+        x + y * CHUNK_SIZE
+    }
+}
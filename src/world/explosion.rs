@@ -0,0 +1,184 @@
+use bevy::{math::IRect, prelude::*, utils::HashMap};
+
+use super::{World, WorldTile};
+
+/// Materials softer than this are treated as if they had this hardness,
+/// so that explosions in soft materials still have a bounded radius
+const MIN_HARDNESS: f32 = 0.5;
+
+/// The width of the ring of scorched tiles around a crater
+const SCORCH_WIDTH: f32 = 1.5;
+
+/// Describes what an explosion did to the world
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ExplosionReport {
+    /// How many tiles of each material were destroyed
+    pub destroyed: HashMap<WorldTile, usize>,
+    /// The smallest rect containing every modified tile (inclusive), or `None` if nothing was modified
+    pub dirty_rect: Option<IRect>,
+}
+
+impl ExplosionReport {
+    /// The total amount of destroyed tiles
+    pub fn total_destroyed(&self) -> usize {
+        self.destroyed.values().sum()
+    }
+
+    fn add_destroyed(&mut self, tile: WorldTile, x: isize, y: isize) {
+        *self.destroyed.entry(tile).or_default() += 1;
+        self.mark_dirty(x, y);
+    }
+
+    fn mark_dirty(&mut self, x: isize, y: isize) {
+        let point = IVec2::new(x as i32, y as i32);
+        self.dirty_rect = Some(match self.dirty_rect {
+            Some(rect) => rect.union_point(point),
+            None => IRect::from_corners(point, point),
+        });
+    }
+}
+
+impl World {
+    /// Carves a crater into the world.
+    ///
+    /// Each tile is destroyed if it is closer to the center than `power / hardness`,
+    /// so harder materials result in smaller craters. Soft tiles just outside of the crater get scorched.
+    pub fn explode(
+        &mut self,
+        explosion_x: isize,
+        explosion_y: isize,
+        power: f32,
+    ) -> ExplosionReport {
+        let mut report = ExplosionReport::default();
+        let max_radius = crater_radius(power, 0.0) + SCORCH_WIDTH;
+        self.for_each_in_radius(
+            explosion_x,
+            explosion_y,
+            max_radius,
+            |world, x, y, distance| {
+                let tile = world.get(x, y);
+                if !tile.is_not_air() || !tile.is_destructible() {
+                    return;
+                }
+
+                let radius = crater_radius(power, tile.hardness());
+                if distance <= radius {
+                    world.set(x, y, WorldTile::Air);
+                    report.add_destroyed(tile, x, y);
+                } else if distance <= radius + SCORCH_WIDTH {
+                    if let Some(scorched_tile) = scorched(tile) {
+                        world.set(x, y, scorched_tile);
+                        report.mark_dirty(x, y);
+                    }
+                }
+            },
+        );
+
+        report
+    }
+}
+
+/// The radius in which an explosion destroys tiles of the given hardness
+fn crater_radius(power: f32, hardness: f32) -> f32 {
+    power / hardness.max(MIN_HARDNESS)
+}
+
+/// The tile that a tile turns into when it is next to an explosion
+fn scorched(tile: WorldTile) -> Option<WorldTile> {
+    match tile {
+        WorldTile::Dirt | WorldTile::Sand => Some(WorldTile::Scorched),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the distance of the destroyed tile that is furthest away from the center
+    fn crater_size(world: &World, center: (isize, isize)) -> f32 {
+        let center = Vec2::new(center.0 as f32, center.1 as f32);
+        (0..40)
+            .flat_map(|x| (0..40).map(move |y| (x, y)))
+            .filter(|&(x, y)| world.get(x, y) == WorldTile::Air)
+            .map(|(x, y)| Vec2::new(x as f32, y as f32).distance(center))
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn crater_radius_scales_with_hardness() {
+        let mut dirt = World::filled(40, 40, |_, _| WorldTile::Dirt);
+        dirt.explode(20, 20, 6.0);
+        let mut stone = World::filled(40, 40, |_, _| WorldTile::Stone);
+        stone.explode(20, 20, 6.0);
+
+        let dirt_radius = crater_size(&dirt, (20, 20));
+        let stone_radius = crater_size(&stone, (20, 20));
+        // Dirt has a hardness of 1 and stone of 3
+        assert!(dirt_radius <= 6.0 && dirt_radius > 5.0, "{dirt_radius}");
+        assert!(stone_radius <= 2.0 && stone_radius > 1.0, "{stone_radius}");
+    }
+
+    #[test]
+    fn indestructible_tiles_survive() {
+        let mut world = World::filled(40, 40, |_, _| WorldTile::Dirt);
+        for x in 0..40 {
+            world.set(x, 20, WorldTile::Bedrock);
+        }
+        let report = world.explode(20, 20, 8.0);
+
+        assert!((0..40).all(|x| world.get(x, 20) == WorldTile::Bedrock));
+        assert!(!report.destroyed.contains_key(&WorldTile::Bedrock));
+        assert!(report.total_destroyed() > 0);
+
+        let mut bedrock = World::filled(40, 40, |_, _| WorldTile::Bedrock);
+        let report = bedrock.explode(20, 20, 8.0);
+        assert_eq!(report, ExplosionReport::default());
+    }
+
+    #[test]
+    fn report_counts_removed_tiles() {
+        let materials = [
+            WorldTile::Dirt,
+            WorldTile::Stone,
+            WorldTile::Sand,
+            WorldTile::Metal,
+            WorldTile::Water,
+        ];
+        let mut world = World::filled(40, 40, |x, y| {
+            if y < 30 {
+                materials[(x + y) as usize % materials.len()]
+            } else {
+                WorldTile::Air
+            }
+        });
+        let before: Vec<_> = (0..40)
+            .flat_map(|x| (0..40).map(move |y| (x, y)))
+            .map(|(x, y)| ((x, y), world.get(x, y)))
+            .collect();
+
+        let report = world.explode(20, 28, 5.0);
+
+        let mut removed: HashMap<WorldTile, usize> = HashMap::new();
+        let mut dirty_rect: Option<IRect> = None;
+        for ((x, y), tile) in before {
+            let after = world.get(x, y);
+            if after == tile {
+                continue;
+            }
+            if after == WorldTile::Air {
+                *removed.entry(tile).or_default() += 1;
+            }
+            let point = IVec2::new(x as i32, y as i32);
+            dirty_rect = Some(
+                dirty_rect.map_or(IRect::from_corners(point, point), |rect| {
+                    rect.union_point(point)
+                }),
+            );
+        }
+        assert!(!removed.is_empty());
+        assert_eq!(report.destroyed, removed);
+        assert_eq!(report.total_destroyed(), removed.values().sum::<usize>());
+        assert_eq!(report.dirty_rect, dirty_rect);
+    }
+}
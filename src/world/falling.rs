@@ -0,0 +1,239 @@
+use bevy::{
+    math::{IRect, IVec2},
+    prelude::default,
+    utils::HashSet,
+};
+
+use super::{chunk::CHUNK_SIZE, Mobility, World};
+
+/// How far liquids look to the sides for a place where they can fall down
+const MAX_FLOW_DISTANCE: isize = 16;
+
+impl World {
+    /// Moves every unsupported loose or liquid tile in the unsettled regions by one tile.
+    ///
+    /// Only tiles close to tiles that were modified since the last step are simulated,
+    /// so this is cheap to call when nothing is happening.
+    /// Returns whether any tile moved.
+    pub fn simulate_step(&mut self) -> bool {
+        if self.unsettled.is_empty() {
+            return false;
+        }
+        let mut regions: Vec<_> = std::mem::take(&mut self.unsettled).into_iter().collect();
+        // Lower chunks go first, so that tiles are moved from bottom to top like inside of a region
+        regions.sort_by_key(|&((chunk_x, chunk_y), _)| (chunk_y, chunk_x));
+
+        self.simulation_step = self.simulation_step.wrapping_add(1);
+        let mut moved = HashSet::new();
+        for (_, region) in regions {
+            self.simulate_region(region, &mut moved);
+        }
+
+        !moved.is_empty()
+    }
+
+    /// Moves the tiles in the region, skipping and adding to the tiles that already moved in this step
+    fn simulate_region(&mut self, region: IRect, moved: &mut HashSet<(isize, isize)>) {
+        for y in region.min.y..=region.max.y {
+            // Alternate the iteration direction to avoid biasing tiles to one side
+            let left_to_right = (y as usize + self.simulation_step).is_multiple_of(2);
+            for i in 0..=(region.max.x - region.min.x) {
+                let x = if left_to_right {
+                    region.min.x + i
+                } else {
+                    region.max.x - i
+                };
+                let (x, y) = (x as isize, y as isize);
+                if moved.contains(&(x, y)) {
+                    continue;
+                }
+
+                if let Some(target) = self.next_position(x, y, left_to_right) {
+                    let tile = self.get(x, y);
+                    let displaced = self.get(target.0, target.1);
+                    self.set(x, y, displaced);
+                    self.set(target.0, target.1, tile);
+                    moved.insert(target);
+                }
+            }
+        }
+    }
+
+    /// Returns whether there are tiles that might move in the next simulation step
+    pub fn is_settled(&self) -> bool {
+        self.unsettled.is_empty()
+    }
+
+    /// Marks all tiles as settled, so they won't be simulated until they get modified.
+    /// This includes floating islands, which only fall once the terrain around them gets modified.
+    pub fn settle(&mut self) {
+        self.unsettled.clear();
+        self.island_search = default();
+    }
+
+    /// Settles a world that was loaded from a map file or an image.
+    /// Maps are designed as they are, so nothing should start falling down.
+    pub(super) fn settle_loaded_map(&mut self) {
+        self.settle();
+    }
+
+    /// Marks the tile and its neighbors to be simulated in the next step
+    pub(super) fn mark_unsettled(&mut self, x: isize, y: isize) {
+        let (x, y) = (x as i32, y as i32);
+        let bounds = IRect::new(0, 0, self.width as i32 - 1, self.height as i32 - 1);
+        let rect = IRect::new(x - 1, y - 1, x + 1, y + 1).intersect(bounds);
+        // The neighbors may be in up to four different chunks
+        let chunk_size = CHUNK_SIZE as i32;
+        for chunk_y in rect.min.y / chunk_size..=rect.max.y / chunk_size {
+            for chunk_x in rect.min.x / chunk_size..=rect.max.x / chunk_size {
+                let chunk_min = IVec2::new(chunk_x, chunk_y) * chunk_size;
+                let chunk_rect = IRect::from_corners(chunk_min, chunk_min + chunk_size - 1);
+                let part = rect.intersect(chunk_rect);
+                self.unsettled
+                    .entry((chunk_x as usize, chunk_y as usize))
+                    .and_modify(|region| *region = region.union(part))
+                    .or_insert(part);
+            }
+        }
+    }
+
+    /// Calculates where the tile at the given position will move next, if at all
+    fn next_position(&self, x: isize, y: isize, prefer_left: bool) -> Option<(isize, isize)> {
+        let sides: [isize; 2] = if prefer_left { [-1, 1] } else { [1, -1] };
+        match self.get(x, y).mobility() {
+            Mobility::Static => None,
+            Mobility::Loose { repose_height } => {
+                if self.can_move_into(x, y, x, y - 1) {
+                    return Some((x, y - 1));
+                }
+                sides.into_iter().find_map(|dx| {
+                    let can_slide = self.can_move_into(x, y, x + dx, y)
+                        && (1..=repose_height as isize)
+                            .all(|dy| self.can_move_into(x, y, x + dx, y - dy));
+                    can_slide.then_some((x + dx, y - 1))
+                })
+            }
+            Mobility::Liquid => {
+                if self.can_move_into(x, y, x, y - 1) {
+                    return Some((x, y - 1));
+                }
+                let falls_diagonally = sides.into_iter().find(|&dx| {
+                    self.can_move_into(x, y, x + dx, y) && self.can_move_into(x, y, x + dx, y - 1)
+                });
+                if let Some(dx) = falls_diagonally {
+                    return Some((x + dx, y - 1));
+                }
+                // Flow towards a nearby drop, so that the surface levels out
+                let flows = sides.into_iter().find(|&dx| {
+                    (1..=MAX_FLOW_DISTANCE)
+                        .map(|distance| x + dx * distance)
+                        .take_while(|&side_x| self.can_move_into(x, y, side_x, y))
+                        .any(|side_x| self.can_move_into(x, y, side_x, y - 1))
+                });
+                if let Some(dx) = flows {
+                    return Some((x + dx, y));
+                }
+                // Only spread out when pushed by the liquid above, so that puddles settle eventually
+                if self.get(x, y + 1).mobility() != Mobility::Liquid {
+                    return None;
+                }
+                sides
+                    .into_iter()
+                    .find(|&dx| self.can_move_into(x, y, x + dx, y))
+                    .map(|dx| (x + dx, y))
+            }
+        }
+    }
+
+    /// Returns whether the tile at `(x, y)` can swap places with the tile at `(target_x, target_y)`
+    fn can_move_into(&self, x: isize, y: isize, target_x: isize, target_y: isize) -> bool {
+        if !self.contains(target_x, target_y) {
+            return false;
+        }
+        let tile = self.get(x, y);
+        let target = self.get(target_x, target_y);
+        !target.is_not_air()
+            || (target.mobility() == Mobility::Liquid && tile.mobility() != Mobility::Liquid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::WorldTile;
+
+    /// Runs the simulation until nothing moves anymore and returns the number of steps
+    fn simulate_until_settled(world: &mut World) -> usize {
+        for step in 0..1000 {
+            if !world.simulate_step() {
+                assert!(world.is_settled());
+                return step;
+            }
+        }
+        panic!("the world didn't settle");
+    }
+
+    /// Returns the number of tiles of the material in every column
+    fn column_heights(world: &World, tile: WorldTile) -> Vec<usize> {
+        (0..world.width as isize)
+            .map(|x| {
+                (0..world.height as isize)
+                    .filter(|&y| world.get(x, y) == tile)
+                    .count()
+            })
+            .collect()
+    }
+
+    /// A world with a floor of stone, with the given tile in a column on top of it
+    fn column_world(column: std::ops::Range<isize>, tile: WorldTile) -> World {
+        World::filled(40, 40, |x, y| match (x, y) {
+            (_, 0) => WorldTile::Stone,
+            (20, y) if column.contains(&y) => tile,
+            _ => WorldTile::Air,
+        })
+    }
+
+    #[test]
+    fn sand_forms_a_pile() {
+        let mut world = column_world(1..17, WorldTile::Sand);
+        simulate_until_settled(&mut world);
+
+        // Sand slides down until the slope is 45°
+        let heights = column_heights(&world, WorldTile::Sand);
+        assert_eq!(heights[16..25], [0, 1, 2, 3, 4, 3, 2, 1, 0]);
+        assert_eq!(heights.iter().sum::<usize>(), 16);
+    }
+
+    #[test]
+    fn water_spreads_out_flat() {
+        // A basin that is six tiles wide
+        let mut world = column_world(1..13, WorldTile::Water);
+        for y in 1..5 {
+            world.set(16, y, WorldTile::Stone);
+            world.set(23, y, WorldTile::Stone);
+        }
+        simulate_until_settled(&mut world);
+
+        let heights = column_heights(&world, WorldTile::Water);
+        assert_eq!(heights[17..23], [2; 6]);
+        assert_eq!(heights.iter().sum::<usize>(), 12);
+        assert!(!world.simulate_step());
+    }
+
+    #[test]
+    fn settled_tiles_are_not_simulated() {
+        let mut world = column_world(10..12, WorldTile::Sand);
+        world.settle();
+        assert!(world.is_settled());
+        assert!(!world.simulate_step());
+        assert_eq!(world.get(20, 11), WorldTile::Sand);
+
+        // Modifying a neighbor lets the tiles fall again
+        world.set(21, 12, WorldTile::Stone);
+        assert!(world.simulate_step());
+        assert_eq!(world.get(20, 11), WorldTile::Air);
+        simulate_until_settled(&mut world);
+        let on_the_floor = (0..40).filter(|&x| world.get(x, 1) == WorldTile::Sand);
+        assert_eq!(on_the_floor.count(), 2);
+    }
+}
@@ -0,0 +1,162 @@
+use std::path::Path;
+
+use image::{ImageResult, Rgba, RgbaImage};
+
+use super::{World, WorldTile};
+
+/// Pixels with an alpha value below this are treated as air
+const AIR_ALPHA_THRESHOLD: u8 = 128;
+
+/// Maps pixel colors to materials when converting between images and worlds
+#[derive(Debug, Clone)]
+pub struct ImagePalette {
+    /// The materials and their colors.
+    /// When importing, every pixel becomes the material with the closest color.
+    /// When exporting, the first color of each material is used.
+    pub entries: Vec<([u8; 3], WorldTile)>,
+}
+
+impl Default for ImagePalette {
+    /// A palette that uses the colors that the materials are rendered with
+    fn default() -> Self {
+        let entries = WorldTile::ALL
+            .into_iter()
+            .filter(|tile| tile.is_not_air())
+            .map(|tile| {
+                let [r, g, b, _] = tile.color().as_rgba_u8();
+                ([r, g, b], tile)
+            })
+            .collect();
+        Self { entries }
+    }
+}
+
+impl ImagePalette {
+    /// Returns the material whose color is closest to the given pixel
+    pub fn tile_for_pixel(&self, Rgba([r, g, b, a]): Rgba<u8>) -> WorldTile {
+        if a < AIR_ALPHA_THRESHOLD {
+            return WorldTile::Air;
+        }
+        let distance = |[er, eg, eb]: [u8; 3]| {
+            let dr = er as i32 - r as i32;
+            let dg = eg as i32 - g as i32;
+            let db = eb as i32 - b as i32;
+            dr * dr + dg * dg + db * db
+        };
+        self.entries
+            .iter()
+            .min_by_key(|(color, _)| distance(*color))
+            .map_or(WorldTile::Air, |(_, tile)| *tile)
+    }
+
+    /// Returns the color of the given material, air and materials without a color are transparent
+    pub fn pixel_for_tile(&self, tile: WorldTile) -> Rgba<u8> {
+        self.entries
+            .iter()
+            .find(|(_, entry_tile)| *entry_tile == tile && tile.is_not_air())
+            .map_or(Rgba([0, 0, 0, 0]), |([r, g, b], _)| Rgba([*r, *g, *b, 255]))
+    }
+}
+
+impl World {
+    /// Creates a world with one tile per pixel.
+    /// The top row of the image becomes the top row of the world.
+    pub fn from_image(image: &RgbaImage, palette: &ImagePalette) -> World {
+        let mut world = World::new(image.width() as usize, image.height() as usize);
+        for (x, image_y, pixel) in image.enumerate_pixels() {
+            let y = image.height() - 1 - image_y;
+            world.set(x as isize, y as isize, palette.tile_for_pixel(*pixel));
+        }
+        world.settle_loaded_map();
+        world
+    }
+
+    /// Creates an image with one pixel per tile
+    pub fn to_image(&self, palette: &ImagePalette) -> RgbaImage {
+        RgbaImage::from_fn(self.width as u32, self.height as u32, |x, image_y| {
+            let y = self.height as u32 - 1 - image_y;
+            palette.pixel_for_tile(self.get(x as isize, y as isize))
+        })
+    }
+
+    /// Loads a world from an image file, see [`World::from_image`]
+    pub fn load_image(path: impl AsRef<Path>, palette: &ImagePalette) -> ImageResult<World> {
+        let image = image::open(path)?.to_rgba8();
+        Ok(World::from_image(&image, palette))
+    }
+
+    /// Saves this world as an image file, the format is determined by the file extension
+    pub fn save_image(&self, path: impl AsRef<Path>, palette: &ImagePalette) -> ImageResult<()> {
+        self.to_image(palette).save(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_keeps_all_tiles() {
+        let world = World::filled(30, 20, |x, y| {
+            WorldTile::ALL[((x / 2 + y * 3) % WorldTile::ALL.len() as isize) as usize]
+        });
+        let palette = ImagePalette::default();
+        let image = world.to_image(&palette);
+        assert_eq!(image.dimensions(), (30, 20));
+        // The bottom left tile is the bottom left pixel
+        assert_eq!(
+            image.get_pixel(0, 19),
+            &palette.pixel_for_tile(world.get(0, 0))
+        );
+
+        let loaded = World::from_image(&image, &palette);
+        assert!(loaded.is_settled());
+        for x in 0..30 {
+            for y in 0..20 {
+                assert_eq!(loaded.get(x, y), world.get(x, y), "tile ({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn transparent_pixels_are_air() {
+        let palette = ImagePalette::default();
+        let Rgba([r, g, b, _]) = palette.pixel_for_tile(WorldTile::Stone);
+        assert_eq!(
+            palette.tile_for_pixel(Rgba([r, g, b, 255])),
+            WorldTile::Stone
+        );
+        assert_eq!(
+            palette.tile_for_pixel(Rgba([r, g, b, 128])),
+            WorldTile::Stone
+        );
+        assert_eq!(palette.tile_for_pixel(Rgba([r, g, b, 127])), WorldTile::Air);
+        assert_eq!(palette.tile_for_pixel(Rgba([r, g, b, 0])), WorldTile::Air);
+        assert_eq!(palette.pixel_for_tile(WorldTile::Air), Rgba([0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn pixels_become_the_closest_color() {
+        let palette = ImagePalette {
+            entries: vec![
+                ([0, 0, 0], WorldTile::Bedrock),
+                ([200, 200, 200], WorldTile::Stone),
+                ([200, 150, 0], WorldTile::Dirt),
+            ],
+        };
+        for (pixel, tile) in [
+            ([10, 20, 0], WorldTile::Bedrock),
+            ([255, 255, 255], WorldTile::Stone),
+            ([150, 160, 140], WorldTile::Stone),
+            ([255, 140, 30], WorldTile::Dirt),
+            ([100, 80, 0], WorldTile::Dirt),
+        ] {
+            let [r, g, b] = pixel;
+            assert_eq!(
+                palette.tile_for_pixel(Rgba([r, g, b, 255])),
+                tile,
+                "{pixel:?}"
+            );
+        }
+    }
+}
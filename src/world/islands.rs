@@ -0,0 +1,255 @@
+use std::collections::VecDeque;
+
+use bevy::utils::HashSet;
+
+use super::{Mobility, World, WorldTile};
+
+/// A group of connected solid tiles that is not connected to the ground
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Island {
+    /// The positions of all tiles of this island, sorted from bottom to top
+    pub tiles: Vec<(isize, isize)>,
+}
+
+impl Island {
+    /// Returns whether this island contains tiles that would not fall down on their own
+    pub fn is_rigid(&self, world: &World) -> bool {
+        self.tiles
+            .iter()
+            .any(|&(x, y)| world.get(x, y).mobility() == Mobility::Static)
+    }
+}
+
+/// The state of the search for islands that were cut off by modifications of the world.
+/// The buffers are kept between searches, so that they don't have to be allocated every step.
+#[derive(Debug, Default)]
+pub(super) struct IslandSearch {
+    /// The positions of solid tiles that were removed since the last search.
+    /// Only their neighbors can have become part of a floating island.
+    removed: Vec<(isize, isize)>,
+    /// Tiles whose island was already determined during the current search
+    checked: HashSet<(isize, isize)>,
+    /// Tiles that were visited by the current flood fill
+    visited: HashSet<(isize, isize)>,
+    stack: Vec<(isize, isize)>,
+}
+
+impl World {
+    /// Finds all groups of solid tiles that are neither connected to the bottom row of the world
+    /// nor to an indestructible tile.
+    ///
+    /// This searches the whole world, see [`World::drop_floating_islands`] for islands
+    /// that were cut off by modifications.
+    pub fn find_floating_islands(&self) -> Vec<Island> {
+        let mut visited = vec![false; self.width * self.height];
+        let anchors = (0..self.width as isize)
+            .map(|x| (x, 0))
+            .chain((0..self.height as isize).flat_map(|y| {
+                (0..self.width as isize)
+                    .map(move |x| (x, y))
+                    .filter(|&(x, y)| !self.get(x, y).is_destructible())
+            }))
+            .filter(|&(x, y)| is_solid(self.get(x, y)));
+        for (x, y) in anchors {
+            self.flood_fill(x, y, &mut visited);
+        }
+
+        let mut islands = Vec::new();
+        for y in 0..self.height as isize {
+            for x in 0..self.width as isize {
+                if is_solid(self.get(x, y)) && !visited[self.tile_index(x, y)] {
+                    let mut tiles = self.flood_fill(x, y, &mut visited);
+                    tiles.sort_by_key(|&(x, y)| (y, x));
+                    islands.push(Island { tiles });
+                }
+            }
+        }
+        islands
+    }
+
+    /// Moves every floating island that contains static tiles down by one tile.
+    /// Islands that consist only of loose tiles are left to [`World::simulate_step`].
+    /// Returns whether any island moved.
+    ///
+    /// Only islands next to solid tiles that were removed since the last call are found,
+    /// so this is cheap to call every step.
+    pub fn drop_floating_islands(&mut self) -> bool {
+        let islands: Vec<_> = self
+            .find_detached_islands()
+            .into_iter()
+            .filter(|island| island.is_rigid(self))
+            .collect();
+        for island in &islands {
+            self.drop_island(island);
+        }
+        !islands.is_empty()
+    }
+
+    /// Moves the island down by one tile.
+    /// The tiles below the island (air or liquids) are moved to the top of the island.
+    fn drop_island(&mut self, island: &Island) {
+        // Since the tiles are sorted from bottom to top, swapping every tile with the one below it
+        // moves the displaced tile up through the whole island
+        for &(x, y) in &island.tiles {
+            let below = self.get(x, y - 1);
+            let tile = self.get(x, y);
+            self.set(x, y - 1, tile);
+            self.set(x, y, below);
+        }
+    }
+
+    /// Remembers that a solid tile was removed, which may have cut off the tiles around it
+    pub(super) fn mark_detachable(
+        &mut self,
+        x: isize,
+        y: isize,
+        old_tile: WorldTile,
+        tile: WorldTile,
+    ) {
+        if is_solid(old_tile) && !is_solid(tile) {
+            self.island_search.removed.push((x, y));
+        }
+    }
+
+    /// Finds the floating islands that contain a neighbor of a tile that was removed since the last call
+    fn find_detached_islands(&mut self) -> Vec<Island> {
+        let mut search = std::mem::take(&mut self.island_search);
+        let mut islands = Vec::new();
+        let mut removed = std::mem::take(&mut search.removed);
+        for &(x, y) in &removed {
+            for start in [(x, y + 1), (x - 1, y), (x, y - 1), (x + 1, y)] {
+                if search.checked.contains(&start) {
+                    continue;
+                }
+                if let Some(mut tiles) = self.floating_island_at(start, &mut search) {
+                    tiles.sort_by_key(|&(x, y)| (y, x));
+                    islands.push(Island { tiles });
+                }
+                search.checked.extend(search.visited.drain());
+            }
+        }
+        search.checked.clear();
+        removed.clear();
+        search.removed = removed;
+        self.island_search = search;
+        islands
+    }
+
+    /// Returns the tiles of the island containing the start tile, if the island is floating.
+    ///
+    /// The flood fill stops as soon as it reaches an anchor or a tile that is known to be grounded.
+    /// Tiles below are visited first, because that's the shortest way to the ground in most terrain.
+    fn floating_island_at(
+        &self,
+        start: (isize, isize),
+        search: &mut IslandSearch,
+    ) -> Option<Vec<(isize, isize)>> {
+        search.stack.clear();
+        search.stack.push(start);
+        let mut tiles = Vec::new();
+        while let Some((x, y)) = search.stack.pop() {
+            if !self.contains(x, y) || !is_solid(self.get(x, y)) || search.visited.contains(&(x, y))
+            {
+                continue;
+            }
+            // Every island that was checked before and is reached again must be grounded,
+            // because floating islands are always visited completely
+            if y == 0 || !self.get(x, y).is_destructible() || search.checked.contains(&(x, y)) {
+                return None;
+            }
+            search.visited.insert((x, y));
+            tiles.push((x, y));
+            search
+                .stack
+                .extend([(x, y + 1), (x - 1, y), (x + 1, y), (x, y - 1)]);
+        }
+        (!tiles.is_empty()).then_some(tiles)
+    }
+
+    /// Returns a unique index for every tile, used to mark visited tiles
+    fn tile_index(&self, x: isize, y: isize) -> usize {
+        x as usize + y as usize * self.width
+    }
+
+    /// Marks every solid tile connected to the start tile as visited and returns the newly visited tiles
+    fn flood_fill(&self, x: isize, y: isize, visited: &mut [bool]) -> Vec<(isize, isize)> {
+        let mut tiles = Vec::new();
+        let mut queue = VecDeque::from([(x, y)]);
+        while let Some((x, y)) = queue.pop_front() {
+            if !self.contains(x, y) || !is_solid(self.get(x, y)) {
+                continue;
+            }
+            let index = self.tile_index(x, y);
+            if visited[index] {
+                continue;
+            }
+            visited[index] = true;
+            tiles.push((x, y));
+            queue.extend([(x, y + 1), (x - 1, y), (x, y - 1), (x + 1, y)]);
+        }
+        tiles
+    }
+}
+
+fn is_solid(tile: WorldTile) -> bool {
+    tile.is_not_air() && tile.mobility() != Mobility::Liquid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A stone floor with a pillar that carries a wide block on top
+    fn pillar_world() -> World {
+        let mut world = World::filled(40, 40, |x, y| match (x, y) {
+            (_, 0..=1) | (20, 2..=9) | (16..=24, 10..=12) => WorldTile::Stone,
+            _ => WorldTile::Air,
+        });
+        world.settle();
+        world
+    }
+
+    #[test]
+    fn cut_off_island_falls_to_the_ground() {
+        let mut world = pillar_world();
+        assert!(world.find_floating_islands().is_empty());
+        for y in 4..=7 {
+            world.set(20, y, WorldTile::Air);
+        }
+        let islands = world.find_floating_islands();
+        assert_eq!(islands.len(), 1);
+        assert_eq!(islands[0].tiles.len(), 2 + 9 * 3);
+        assert_eq!(islands[0].tiles[0], (20, 8));
+
+        // The island drops one tile per step until it rests on the rest of the pillar
+        for step in 1..=4 {
+            assert!(world.drop_floating_islands());
+            assert_eq!(world.get(20, 8 - step), WorldTile::Stone);
+            assert_eq!(world.get(20, 12 - step), WorldTile::Stone);
+            assert_eq!(world.get(20, 13 - step), WorldTile::Air);
+        }
+        assert!(!world.drop_floating_islands());
+        assert!(world.find_floating_islands().is_empty());
+        assert_eq!(world.get(20, 3), WorldTile::Stone);
+        assert_eq!(world.get(16, 6), WorldTile::Stone);
+    }
+
+    #[test]
+    fn anchored_tiles_are_never_floating() {
+        // Stone hanging from bedrock and stone standing on the bottom of the world, both with a branch
+        let mut world = World::filled(40, 40, |x, y| match (x, y) {
+            (10, 30) => WorldTile::Bedrock,
+            (10, 20..=29) | (11, 20) | (30, 0..=10) | (31, 10) => WorldTile::Stone,
+            _ => WorldTile::Air,
+        });
+        world.settle();
+        // The tiles next to the removed branches are still anchored
+        world.set(11, 20, WorldTile::Air);
+        world.set(31, 10, WorldTile::Air);
+
+        assert!(world.find_floating_islands().is_empty());
+        assert!(!world.drop_floating_islands());
+        assert_eq!(world.get(10, 20), WorldTile::Stone);
+        assert_eq!(world.get(30, 10), WorldTile::Stone);
+    }
+}
@@ -0,0 +1,278 @@
+//! A binary file format for worlds.
+//!
+//! All numbers are little endian. A map file consists of
+//! - the magic bytes `TMAP`
+//! - the format version as `u16`
+//! - the width and height of the world as `u32`
+//! - the palette: the amount of materials as `u8`, followed by the name of each material,
+//!   encoded as a `u8` length and the utf-8 bytes
+//! - the run-length encoded tiles, row by row starting at the bottom left.
+//!   Each run is a `u32` length followed by the `u8` palette index of the material.
+
+use std::{
+    fmt,
+    io::{self, Read, Write},
+};
+
+use super::{World, WorldTile};
+
+const MAGIC: [u8; 4] = *b"TMAP";
+const VERSION: u16 = 1;
+/// The largest width or height of a world that can be saved and loaded, in tiles
+pub const MAX_SIDE_LENGTH: usize = 1 << 16;
+/// The largest amount of tiles of a world that can be saved and loaded.
+/// This keeps corrupt files from allocating huge amounts of memory.
+pub const MAX_TILE_COUNT: usize = 1 << 26;
+
+/// An error that occurred while loading a map
+#[derive(Debug)]
+pub enum MapError {
+    Io(io::Error),
+    /// The data does not start with the magic bytes
+    NotAMap,
+    UnsupportedVersion(u16),
+    UnknownMaterial(String),
+    /// The data describes a world that can't be loaded
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::Io(error) => write!(f, "Could not read map: {error}"),
+            MapError::NotAMap => write!(f, "The file is not a map"),
+            MapError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported map version {version}, expected {VERSION}")
+            }
+            MapError::UnknownMaterial(name) => write!(f, "Unknown material '{name}'"),
+            MapError::Corrupt(reason) => write!(f, "The map is corrupt: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+impl From<io::Error> for MapError {
+    fn from(error: io::Error) -> Self {
+        MapError::Io(error)
+    }
+}
+
+impl World {
+    /// Writes this world in the map format.
+    /// Fails without writing anything if the world is too large to be loaded again.
+    pub fn save(&self, mut writer: impl Write) -> io::Result<()> {
+        check_size(self.width, self.height)
+            .map_err(|reason| io::Error::new(io::ErrorKind::InvalidInput, reason))?;
+
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&(self.width as u32).to_le_bytes())?;
+        writer.write_all(&(self.height as u32).to_le_bytes())?;
+
+        // Every material is part of the palette, the few bytes are not worth searching for the used ones
+        writer.write_all(&[WorldTile::ALL.len() as u8])?;
+        for tile in WorldTile::ALL {
+            writer.write_all(&[tile.name().len() as u8])?;
+            writer.write_all(tile.name().as_bytes())?;
+        }
+
+        let mut run: Option<(u32, WorldTile)> = None;
+        for y in 0..self.height as isize {
+            for x in 0..self.width as isize {
+                let tile = self.get(x, y);
+                run = match run {
+                    Some((length, run_tile)) if run_tile == tile && length < u32::MAX => {
+                        Some((length + 1, run_tile))
+                    }
+                    Some(finished_run) => {
+                        write_run(&mut writer, finished_run)?;
+                        Some((1, tile))
+                    }
+                    None => Some((1, tile)),
+                };
+            }
+        }
+        if let Some(run) = run {
+            write_run(&mut writer, run)?;
+        }
+
+        writer.flush()
+    }
+
+    /// Reads a world that was written by [`World::save`]
+    pub fn load(mut reader: impl Read) -> Result<World, MapError> {
+        let magic: [u8; 4] = read_bytes(&mut reader)?;
+        if magic != MAGIC {
+            return Err(MapError::NotAMap);
+        }
+        let version = u16::from_le_bytes(read_bytes(&mut reader)?);
+        if version != VERSION {
+            return Err(MapError::UnsupportedVersion(version));
+        }
+        let width = u32::from_le_bytes(read_bytes(&mut reader)?) as usize;
+        let height = u32::from_le_bytes(read_bytes(&mut reader)?) as usize;
+        let tile_count = check_size(width, height).map_err(MapError::Corrupt)?;
+
+        let [palette_len] = read_bytes(&mut reader)?;
+        let mut palette = Vec::with_capacity(palette_len as usize);
+        for _ in 0..palette_len {
+            let [name_len] = read_bytes(&mut reader)?;
+            let mut name = vec![0; name_len as usize];
+            reader.read_exact(&mut name)?;
+            let name = String::from_utf8(name)
+                .map_err(|_| MapError::Corrupt("material name is not utf-8"))?;
+            let tile = WorldTile::from_name(&name).ok_or(MapError::UnknownMaterial(name))?;
+            palette.push(tile);
+        }
+
+        let mut world = World::new(width, height);
+        let mut index = 0;
+        while index < tile_count {
+            let length = u32::from_le_bytes(read_bytes(&mut reader)?) as usize;
+            let [palette_index] = read_bytes(&mut reader)?;
+            let tile = *palette
+                .get(palette_index as usize)
+                .ok_or(MapError::Corrupt("palette index out of range"))?;
+            if length == 0 || length > tile_count - index {
+                return Err(MapError::Corrupt("invalid run length"));
+            }
+
+            if tile.is_not_air() {
+                for tile_index in index..(index + length) {
+                    let x = (tile_index % width) as isize;
+                    let y = (tile_index / width) as isize;
+                    world.set(x, y, tile);
+                }
+            }
+            index += length;
+        }
+        world.settle_loaded_map();
+
+        Ok(world)
+    }
+}
+
+/// Checks that a world of this size can be stored in a map and returns the amount of tiles
+pub fn check_size(width: usize, height: usize) -> Result<usize, &'static str> {
+    if !(1..=MAX_SIDE_LENGTH).contains(&width) || !(1..=MAX_SIDE_LENGTH).contains(&height) {
+        return Err("invalid world size");
+    }
+    width
+        .checked_mul(height)
+        .filter(|&tile_count| tile_count <= MAX_TILE_COUNT)
+        .ok_or("world is too large")
+}
+
+fn write_run(writer: &mut impl Write, (length, tile): (u32, WorldTile)) -> io::Result<()> {
+    writer.write_all(&length.to_le_bytes())?;
+    // The palette contains all materials in order, so the palette index is the discriminant
+    writer.write_all(&[tile as u8])
+}
+
+fn read_bytes<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A small world that contains every material, with runs of different lengths
+    fn test_world() -> World {
+        World::filled(70, 40, |x, y| {
+            WorldTile::ALL[((x / 3 + y * 5) % WorldTile::ALL.len() as isize) as usize]
+        })
+    }
+
+    fn save(world: &World) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        world.save(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn assert_same_tiles(world: &World, other: &World) {
+        assert_eq!((world.width, world.height), (other.width, other.height));
+        for x in 0..world.width as isize {
+            for y in 0..world.height as isize {
+                assert_eq!(world.get(x, y), other.get(x, y), "tile ({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip_keeps_all_tiles() {
+        let world = test_world();
+        let loaded = World::load(save(&world).as_slice()).unwrap();
+        assert_same_tiles(&world, &loaded);
+
+        let generated = World::generate(&Default::default());
+        let loaded = World::load(save(&generated).as_slice()).unwrap();
+        assert_same_tiles(&generated, &loaded);
+    }
+
+    #[test]
+    fn truncated_file_is_an_error() {
+        let bytes = save(&test_world());
+        for length in [0, 3, 8, 14, 20, bytes.len() / 2, bytes.len() - 1] {
+            assert!(
+                World::load(&bytes[..length]).is_err(),
+                "loading {length} of {} bytes succeeded",
+                bytes.len()
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_header_is_an_error() {
+        let bytes = save(&test_world());
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert!(matches!(
+            World::load(bad_magic.as_slice()),
+            Err(MapError::NotAMap)
+        ));
+
+        let mut unknown_version = bytes.clone();
+        unknown_version[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            World::load(unknown_version.as_slice()),
+            Err(MapError::UnsupportedVersion(version)) if version == VERSION + 1
+        ));
+
+        for (width, height) in [(0, 40), (70, 0), (u32::MAX, u32::MAX), (1 << 16, 1 << 16)] {
+            let mut invalid_size = bytes.clone();
+            invalid_size[6..10].copy_from_slice(&width.to_le_bytes());
+            invalid_size[10..14].copy_from_slice(&height.to_le_bytes());
+            assert!(matches!(
+                World::load(invalid_size.as_slice()),
+                Err(MapError::Corrupt(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn worlds_that_cant_be_loaded_are_not_saved() {
+        for (width, height) in [
+            (0, 40),
+            (70, 0),
+            (MAX_SIDE_LENGTH + 1, 1),
+            (MAX_SIDE_LENGTH, MAX_SIDE_LENGTH),
+        ] {
+            let mut bytes = Vec::new();
+            let error = World::new(width, height).save(&mut bytes).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+            assert!(bytes.is_empty());
+        }
+
+        let largest = World::new(MAX_SIDE_LENGTH, MAX_TILE_COUNT / MAX_SIDE_LENGTH);
+        let loaded = World::load(save(&largest).as_slice()).unwrap();
+        assert_eq!(
+            (loaded.width, loaded.height),
+            (largest.width, largest.height)
+        );
+    }
+}
@@ -0,0 +1,360 @@
+use bevy::prelude::*;
+
+use super::World;
+
+/// A tile with a collider that was found by a query.
+///
+/// Queries use the [`super::tile_shape::TileShape`] of the tiles, so they agree with the collisions and with what is drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileHit {
+    pub tile: (isize, isize),
+    /// Where the ray entered the tile, or the point of the tile that is closest to the center of the shape
+    pub point: Vec2,
+    /// The unit vector that points away from the tile towards the origin of the query
+    pub normal: Vec2,
+    /// The distance from the origin of the ray or the center of the shape to the point
+    pub distance: f32,
+}
+
+/// How far a ray is moved into a tile to test whether it entered the tile through a solid side
+const ENTRY_NUDGE: f32 = 1e-4;
+
+impl World {
+    /// Returns the first tile with a collider whose shape the ray passes through,
+    /// if it is at most `max_distance` tiles away from the origin.
+    ///
+    /// The tiles are visited one by one along the ray (DDA), so the cost only depends on the distance.
+    /// If the origin is inside of a tile, that tile is hit at a distance of 0.
+    pub fn raycast(&self, origin: Vec2, direction: Vec2, max_distance: f32) -> Option<TileHit> {
+        let direction = direction.try_normalize()?;
+        let (mut x, mut y) = (origin.x.floor() as isize, origin.y.floor() as isize);
+
+        let step = (
+            if direction.x < 0.0 { -1 } else { 1 },
+            if direction.y < 0.0 { -1 } else { 1 },
+        );
+        // The distance along the ray between two tile borders on each axis
+        let delta = direction.abs().recip();
+        // The distance along the ray to the next tile border on each axis
+        let border_distance = |position: f32, tile: isize, step: isize, delta: f32| {
+            if delta.is_infinite() {
+                f32::INFINITY
+            } else if step > 0 {
+                (tile as f32 + 1.0 - position) * delta
+            } else {
+                (position - tile as f32) * delta
+            }
+        };
+        let mut next_x = border_distance(origin.x, x, step.0, delta.x);
+        let mut next_y = border_distance(origin.y, y, step.1, delta.y);
+
+        // Where the ray entered the current tile, and the normal of the side that it entered through
+        let (mut distance, mut normal) = (0.0, -direction);
+        loop {
+            let exit = next_x.min(next_y).min(max_distance);
+            let ray = TileRay {
+                origin,
+                direction,
+                enter: distance,
+                exit,
+                enter_normal: normal,
+            };
+            if let Some(hit) = self.raycast_tile((x, y), ray) {
+                return Some(hit);
+            }
+
+            (distance, normal) = if next_x < next_y {
+                x += step.0;
+                next_x += delta.x;
+                (next_x - delta.x, Vec2::new(-step.0 as f32, 0.0))
+            } else {
+                y += step.1;
+                next_y += delta.y;
+                (next_y - delta.y, Vec2::new(0.0, -step.1 as f32))
+            };
+            if distance > max_distance || self.is_leaving(x, y, step) {
+                return None;
+            }
+        }
+    }
+
+    /// Returns where the part of the ray inside of the tile hits the shape of the tile
+    fn raycast_tile(&self, tile: (isize, isize), ray: TileRay) -> Option<TileHit> {
+        let world_tile = self.get_rendered(tile.0, tile.1);
+        if !world_tile.tile.has_collider() {
+            return None;
+        }
+        let shape = world_tile.shape();
+        let offset = Vec2::new(tile.0 as f32, tile.1 as f32);
+
+        // The ray may start inside of the shape or enter it through a side that is covered by the shape
+        let entry = ray.origin + ray.direction * ray.enter;
+        if shape.contains(entry + ray.direction * ENTRY_NUDGE - offset) {
+            return Some(TileHit {
+                tile,
+                point: entry,
+                normal: ray.enter_normal,
+                distance: ray.enter,
+            });
+        }
+
+        shape
+            .edges()
+            .into_iter()
+            .filter_map(|(start, end)| {
+                let (start, end) = (start + offset, end + offset);
+                let normal = (end - start).perp().normalize();
+                // Edges are only hit from the outside
+                if ray.direction.dot(normal) >= 0.0 {
+                    return None;
+                }
+                let distance = ray_segment_distance(ray.origin, ray.direction, start, end)?;
+                (ray.enter..=ray.exit)
+                    .contains(&distance)
+                    .then_some(TileHit {
+                        tile,
+                        point: ray.origin + ray.direction * distance,
+                        normal,
+                        distance,
+                    })
+            })
+            .min_by(|a, b| a.distance.total_cmp(&b.distance))
+    }
+
+    /// Returns every tile with a collider whose shape overlaps the rect. Touching the rect doesn't count.
+    pub fn overlap_rect(&self, rect: Rect) -> Vec<TileHit> {
+        self.tiles_in_rect(rect)
+            .filter(|&(x, y)| {
+                let offset = Vec2::new(x as f32, y as f32);
+                let local_rect = Rect::from_corners(rect.min - offset, rect.max - offset);
+                self.get_rendered(x, y).shape().overlaps_rect(local_rect)
+            })
+            .map(|tile| self.overlap_hit(tile, rect.center()))
+            .collect()
+    }
+
+    /// Returns every tile with a collider whose shape overlaps the circle. Touching the circle doesn't count.
+    pub fn overlap_circle(&self, center: Vec2, radius: f32) -> Vec<TileHit> {
+        let bounds = Rect::from_center_half_size(center, Vec2::splat(radius));
+        self.tiles_in_rect(bounds)
+            .map(|tile| self.overlap_hit(tile, center))
+            .filter(|hit| hit.distance < radius)
+            .collect()
+    }
+
+    /// Returns the positions of all tiles with a collider that overlap the rect
+    fn tiles_in_rect(&self, rect: Rect) -> impl Iterator<Item = (isize, isize)> + '_ {
+        let (min_x, min_y) = (rect.min.x.floor() as isize, rect.min.y.floor() as isize);
+        let (max_x, max_y) = (rect.max.x.ceil() as isize, rect.max.y.ceil() as isize);
+        (min_x..max_x)
+            .flat_map(move |x| (min_y..max_y).map(move |y| (x, y)))
+            .filter(|(x, y)| self.get(*x, *y).has_collider())
+    }
+
+    /// Describes the overlap of the shape of a tile with a shape around the center
+    fn overlap_hit(&self, (x, y): (isize, isize), center: Vec2) -> TileHit {
+        let offset = Vec2::new(x as f32, y as f32);
+        let point = self
+            .get_rendered(x, y)
+            .shape()
+            .closest_point(center - offset)
+            .map_or(center, |point| point + offset);
+        // Shapes with the center inside of the tile are pushed out away from the center of the tile
+        let normal = (center - point)
+            .try_normalize()
+            .unwrap_or_else(|| (center - (offset + 0.5)).normalize_or_zero());
+        TileHit {
+            tile: (x, y),
+            point,
+            normal,
+            distance: center.distance(point),
+        }
+    }
+
+    /// Returns whether a ray at the given tile has left the world and won't come back
+    fn is_leaving(&self, x: isize, y: isize, (step_x, step_y): (isize, isize)) -> bool {
+        (x < 0 && step_x < 0)
+            || (x >= self.width as isize && step_x > 0)
+            || (y < 0 && step_y < 0)
+            || (y >= self.height as isize && step_y > 0)
+    }
+}
+
+/// The part of a ray that is inside of a single tile, see [`World::raycast`]
+#[derive(Debug, Clone, Copy)]
+struct TileRay {
+    origin: Vec2,
+    /// The direction of the ray as a unit vector
+    direction: Vec2,
+    /// The distance along the ray at which it enters the tile
+    enter: f32,
+    /// The distance along the ray at which it leaves the tile
+    exit: f32,
+    /// The normal of the side of the tile that the ray enters through
+    enter_normal: Vec2,
+}
+
+/// Returns the distance along the ray at which it crosses the segment, if it does
+fn ray_segment_distance(origin: Vec2, direction: Vec2, start: Vec2, end: Vec2) -> Option<f32> {
+    let segment = end - start;
+    let denominator = direction.perp_dot(segment);
+    if denominator == 0.0 {
+        return None;
+    }
+    let to_start = start - origin;
+    let distance = to_start.perp_dot(segment) / denominator;
+    let along_segment = to_start.perp_dot(direction) / denominator;
+    (distance >= 0.0 && (0.0..=1.0).contains(&along_segment)).then_some(distance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::WorldTile;
+
+    /// A slope of 45° going down to the right on top of flat ground, next to a wall.
+    /// The surface of the slope is the diagonal x + y = 21.
+    fn test_world() -> World {
+        World::filled(60, 40, |x, y| {
+            if y <= 20 - x || y < 10 || (x >= 50 && y < 30) {
+                WorldTile::Dirt
+            } else {
+                WorldTile::Air
+            }
+        })
+    }
+
+    /// Whether the point is inside of the shape of a tile with a collider
+    fn is_solid_at(world: &World, point: Vec2) -> bool {
+        let (x, y) = (point.x.floor() as isize, point.y.floor() as isize);
+        let tile = world.get_rendered(x, y);
+        tile.tile.has_collider() && tile.shape().contains(point - Vec2::new(x as f32, y as f32))
+    }
+
+    /// Walks along the ray in small steps until it is inside of a tile shape
+    fn sampled_raycast(
+        world: &World,
+        origin: Vec2,
+        direction: Vec2,
+        max_distance: f32,
+    ) -> Option<f32> {
+        const STEP: f32 = 1e-3;
+        let direction = direction.normalize();
+        (0..=(max_distance / STEP) as usize)
+            .map(|step| step as f32 * STEP)
+            .find(|&distance| is_solid_at(world, origin + direction * distance))
+    }
+
+    /// Whether any point on a grid inside of the rect is inside of a tile shape
+    fn sampled_overlap(world: &World, rect: Rect) -> bool {
+        (0..=10).any(|i| {
+            (0..=10).any(|j| {
+                let point = rect.min + rect.size() * Vec2::new(i as f32, j as f32) / 10.0;
+                is_solid_at(world, point)
+            })
+        })
+    }
+
+    #[test]
+    fn ray_hits_the_diagonal_edge_of_slopes() {
+        let world = test_world();
+        // Straight down onto the empty upper right corner of the tile at (5, 15)
+        let hit = world
+            .raycast(Vec2::new(5.75, 30.0), Vec2::NEG_Y, 100.0)
+            .unwrap();
+        assert_eq!(hit.tile, (5, 15));
+        assert!((hit.distance - 14.75).abs() < 1e-4, "{}", hit.distance);
+        assert!(hit.normal.distance(Vec2::ONE.normalize()) < 1e-4);
+
+        for origin in [
+            Vec2::new(5.3, 30.0),
+            Vec2::new(25.0, 25.0),
+            Vec2::new(2.5, 19.5),
+        ] {
+            for angle in (0..36).map(|i| (i as f32 * 10.0 + 5.0).to_radians()) {
+                let direction = Vec2::from_angle(angle);
+                let expected = sampled_raycast(&world, origin, direction, 40.0);
+                let hit = world.raycast(origin, direction, 40.0);
+                match (hit, expected) {
+                    (Some(hit), Some(expected)) => assert!(
+                        (hit.distance - expected).abs() < 2e-3,
+                        "{origin} {direction}: {} != {expected}",
+                        hit.distance
+                    ),
+                    (None, None) => {}
+                    _ => panic!("{origin} {direction}: {hit:?} != {expected:?}"),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ray_starting_inside_hits_immediately() {
+        let world = test_world();
+        for origin in [
+            Vec2::new(3.5, 3.5),
+            Vec2::new(5.2, 15.2),
+            Vec2::new(50.0, 20.0),
+        ] {
+            let hit = world.raycast(origin, Vec2::new(0.3, 1.0), 10.0).unwrap();
+            assert_eq!(hit.distance, 0.0);
+            assert_eq!(hit.point, origin);
+        }
+    }
+
+    #[test]
+    fn rays_along_tile_borders() {
+        let world = test_world();
+        // Down along the border between two columns of the flat ground
+        let hit = world
+            .raycast(Vec2::new(30.0, 25.0), Vec2::NEG_Y, 100.0)
+            .unwrap();
+        assert_eq!(
+            (hit.point, hit.normal, hit.distance),
+            (Vec2::new(30.0, 10.0), Vec2::Y, 15.0)
+        );
+        // Right along the surface of the ground, which it only touches, until it reaches the wall
+        let hit = world
+            .raycast(Vec2::new(25.0, 10.0), Vec2::X, 100.0)
+            .unwrap();
+        assert_eq!(
+            (hit.point, hit.normal, hit.distance),
+            (Vec2::new(50.0, 10.0), Vec2::NEG_X, 25.0)
+        );
+        // Up along the side of the wall, out of the world
+        assert_eq!(world.raycast(Vec2::new(50.0, 35.0), Vec2::Y, 100.0), None);
+    }
+
+    #[test]
+    fn overlaps_ignore_the_empty_corners_of_slopes() {
+        let world = test_world();
+        // Inside of the bounds of the tile at (5, 15), but above its diagonal
+        let corner = Rect::new(5.7, 15.7, 5.95, 15.95);
+        assert!(world.overlap_rect(corner).is_empty());
+        assert!(world.overlap_circle(Vec2::new(5.9, 15.9), 0.2).is_empty());
+        let solid = Rect::new(5.1, 15.1, 5.3, 15.3);
+        assert_eq!(world.overlap_rect(solid)[0].tile, (5, 15));
+        assert_eq!(
+            world.overlap_circle(Vec2::new(5.9, 15.9), 0.6)[0].tile,
+            (5, 15)
+        );
+
+        // Rects that clearly overlap a shape or are clearly separated from it agree with the shapes
+        for i in 0..60 {
+            for j in 0..60 {
+                let min = Vec2::new(2.0, 10.0) + Vec2::new(i as f32, j as f32) * 0.17;
+                let rect = Rect::from_corners(min, min + 0.3);
+                let overlaps = !world.overlap_rect(rect).is_empty();
+                let grown = |margin: f32| {
+                    Rect::from_center_half_size(rect.center(), rect.half_size() + margin)
+                };
+                if sampled_overlap(&world, grown(-0.02)) {
+                    assert!(overlaps, "{rect:?}");
+                }
+                if !sampled_overlap(&world, grown(0.02)) {
+                    assert!(!overlaps, "{rect:?}");
+                }
+            }
+        }
+    }
+}
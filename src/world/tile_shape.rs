@@ -0,0 +1,209 @@
+use bevy::math::{Rect, Vec2};
+
+use super::{RenderedWorldTile, WorldTile};
+
+/// The corners of a tile in clockwise order, starting at the top left
+const CORNERS: [Vec2; 4] = [
+    Vec2::new(0.0, 1.0),
+    Vec2::new(1.0, 1.0),
+    Vec2::new(1.0, 0.0),
+    Vec2::new(0.0, 0.0),
+];
+
+/// The shape of a tile, which depends on which of its neighbors are solid.
+///
+/// A tile is divided into four triangles by its diagonals, and the triangles that face a solid
+/// neighbor are filled. Tiles with solid neighbors on three sides or on opposite sides are
+/// filled completely, and tiles without any solid neighbor are a small diamond.
+///
+/// The world shader (`world_mesh_2d.wgsl`) draws tiles with the same rules,
+/// so both have to be changed together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileShape {
+    Empty,
+    Diamond,
+    Triangles {
+        /// Whether the neighbor in each direction is solid, clockwise starting at the top
+        solid_neighbors: [bool; 4],
+        /// Whether the triangle facing each direction is filled, clockwise starting at the top
+        filled: [bool; 4],
+    },
+}
+
+impl TileShape {
+    /// Determines the shape of a tile.
+    /// The neighbors are in the order top - left - bottom - right, like [`super::RenderedWorldTile::neighbors`].
+    pub fn new(is_solid: bool, [top, left, bottom, right]: [bool; 4]) -> Self {
+        if !is_solid {
+            return TileShape::Empty;
+        }
+        let solid_neighbors = [top, right, bottom, left];
+        let total = solid_neighbors.into_iter().filter(|solid| *solid).count();
+        if total == 0 {
+            return TileShape::Diamond;
+        }
+        let is_full = total >= 3 || (top && bottom) || (left && right);
+        TileShape::Triangles {
+            solid_neighbors,
+            filled: solid_neighbors.map(|solid| is_full || solid),
+        }
+    }
+
+    /// Determines the shape of a tile from its material and the materials of its neighbors,
+    /// in the order top - left - bottom - right. See [`connects_to`] for which neighbors count as solid.
+    pub fn of(tile: WorldTile, neighbors: [WorldTile; 4]) -> Self {
+        TileShape::new(
+            tile.is_not_air(),
+            neighbors.map(|neighbor| connects_to(tile, neighbor)),
+        )
+    }
+
+    /// Determines the shape of a tile from a bitset in the order top - left - bottom - right - self,
+    /// which is the format that the world shader uses
+    pub fn from_neighbors_bitset(neighbors: u32) -> Self {
+        let bit = |index: u32| neighbors & (1 << index) != 0;
+        TileShape::new(bit(4), [bit(0), bit(1), bit(2), bit(3)])
+    }
+
+    /// Returns whether this tile covers its whole square
+    pub fn is_full(self) -> bool {
+        matches!(self, TileShape::Triangles { filled, .. } if filled.iter().all(|filled| *filled))
+    }
+
+    /// Returns whether the point in local coordinates is inside of this shape, including its boundary
+    pub fn contains(self, point: Vec2) -> bool {
+        self.parts()
+            .iter()
+            .any(|part| polygon_contains(part, point))
+    }
+
+    /// Returns the point of this shape that is closest to the given point, in local coordinates,
+    /// or `None` if the shape is empty
+    pub fn closest_point(self, point: Vec2) -> Option<Vec2> {
+        self.parts()
+            .iter()
+            .map(|part| polygon_closest_point(part, point))
+            .min_by(|a, b| {
+                a.distance_squared(point)
+                    .total_cmp(&b.distance_squared(point))
+            })
+    }
+
+    /// Returns whether the rect in local coordinates overlaps this shape. Touching it doesn't count.
+    pub fn overlaps_rect(self, rect: Rect) -> bool {
+        self.parts().iter().any(|part| {
+            // Separating axis test, with the axes of the rect and the normals of the polygon
+            let mut axes = vec![Vec2::X, Vec2::Y];
+            axes.extend(polygon_edges(part).map(|(start, end)| (end - start).perp()));
+            axes.into_iter().all(|axis| {
+                let projections = part.iter().map(|corner| corner.dot(axis));
+                let part_min = projections.clone().fold(f32::INFINITY, f32::min);
+                let part_max = projections.fold(f32::NEG_INFINITY, f32::max);
+                let rect_extent = rect.half_size().dot(axis.abs());
+                let rect_center = rect.center().dot(axis);
+                part_min < rect_center + rect_extent && part_max > rect_center - rect_extent
+            })
+        })
+    }
+
+    /// Returns the filled parts of this shape as convex polygons in clockwise order, in local coordinates
+    fn parts(self) -> Vec<Vec<Vec2>> {
+        let center = Vec2::splat(0.5);
+        match self {
+            TileShape::Empty => Vec::new(),
+            TileShape::Diamond => vec![[Vec2::Y, Vec2::X, Vec2::NEG_Y, Vec2::NEG_X]
+                .map(|direction| center + direction * 0.25)
+                .to_vec()],
+            _ if self.is_full() => vec![CORNERS.to_vec()],
+            TileShape::Triangles { filled, .. } => (0..4)
+                .filter(|i| filled[*i])
+                .map(|i| vec![CORNERS[i], CORNERS[(i + 1) % 4], center])
+                .collect(),
+        }
+    }
+
+    /// Returns the edges between this tile and non-solid space, in local coordinates between 0 and 1.
+    ///
+    /// The edges go clockwise around the tile, so the solid side is to the right of each edge.
+    /// Sides that face a solid neighbor are skipped, because that neighbor covers them.
+    pub fn edges(self) -> Vec<(Vec2, Vec2)> {
+        let center = Vec2::splat(0.5);
+        match self {
+            TileShape::Empty => Vec::new(),
+            TileShape::Diamond => {
+                let corners = [Vec2::Y, Vec2::X, Vec2::NEG_Y, Vec2::NEG_X]
+                    .map(|direction| center + direction * 0.25);
+                (0..4).map(|i| (corners[i], corners[(i + 1) % 4])).collect()
+            }
+            TileShape::Triangles {
+                solid_neighbors,
+                filled,
+            } => {
+                let mut edges = Vec::new();
+                for i in (0..4).filter(|i| filled[*i]) {
+                    let next = (i + 1) % 4;
+                    let previous = (i + 3) % 4;
+                    if !solid_neighbors[i] {
+                        edges.push((CORNERS[i], CORNERS[next]));
+                    }
+                    // The diagonals are only edges if the triangle on the other side is empty
+                    if !filled[next] {
+                        edges.push((CORNERS[next], center));
+                    }
+                    if !filled[previous] {
+                        edges.push((center, CORNERS[i]));
+                    }
+                }
+                edges
+            }
+        }
+    }
+}
+
+/// Returns the edges of a polygon, including the one from the last to the first corner
+fn polygon_edges(polygon: &[Vec2]) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+    (0..polygon.len()).map(|i| (polygon[i], polygon[(i + 1) % polygon.len()]))
+}
+
+/// Returns whether the point is inside of the convex polygon with clockwise corners
+fn polygon_contains(polygon: &[Vec2], point: Vec2) -> bool {
+    polygon_edges(polygon).all(|(start, end)| (end - start).perp_dot(point - start) <= 0.0)
+}
+
+/// Returns the point of the convex polygon with clockwise corners that is closest to the given point
+fn polygon_closest_point(polygon: &[Vec2], point: Vec2) -> Vec2 {
+    if polygon_contains(polygon, point) {
+        return point;
+    }
+    polygon_edges(polygon)
+        .map(|(start, end)| {
+            let direction = end - start;
+            let t = ((point - start).dot(direction) / direction.length_squared()).clamp(0.0, 1.0);
+            start + direction * t
+        })
+        .min_by(|a, b| {
+            a.distance_squared(point)
+                .total_cmp(&b.distance_squared(point))
+        })
+        .unwrap_or(point)
+}
+
+impl RenderedWorldTile {
+    /// The shape that this tile is drawn with and that bodies collide with
+    pub fn shape(&self) -> TileShape {
+        TileShape::of(self.tile, self.neighbors)
+    }
+}
+
+/// Returns whether the shape of `tile` treats `neighbor` as a solid neighbor.
+///
+/// Tiles with a collider only connect to other tiles with a collider, so that they keep their edges
+/// next to water and are drawn exactly like they collide. Tiles without a collider, like water,
+/// connect to every tile that isn't air, so that they fill the space up to the terrain.
+pub fn connects_to(tile: WorldTile, neighbor: WorldTile) -> bool {
+    if tile.has_collider() {
+        neighbor.has_collider()
+    } else {
+        neighbor.is_not_air()
+    }
+}
@@ -0,0 +1,347 @@
+use std::f32::consts::TAU;
+
+use bevy::math::Vec2;
+
+use crate::math::{value_noise_2d, Rng};
+
+use super::{World, WorldTile};
+
+/// Configures how [`World::generate`] creates a world
+#[derive(Debug, Clone)]
+pub struct WorldGenConfig {
+    pub width: usize,
+    pub height: usize,
+    /// The seed for all random decisions, the same seed always results in the same world
+    pub seed: u64,
+    /// The waves that are added together to form the surface
+    pub octaves: Vec<Octave>,
+    /// The lowest possible surface height, as a fraction of the world height
+    pub min_height: f32,
+    /// The highest possible surface height, as a fraction of the world height
+    pub max_height: f32,
+    /// The materials below the surface, from top to bottom.
+    /// The last layer extends to the bottom of the world.
+    pub layers: Vec<Layer>,
+    /// The height of the indestructible floor of the world in tiles, `None` for no floor
+    pub bedrock: Option<Variation>,
+    /// Pockets of other materials that are scattered below the surface
+    pub ores: Vec<OreConfig>,
+    /// Configures the caves that get carved out of the terrain, `None` for no caves
+    pub caves: Option<CaveConfig>,
+}
+
+impl Default for WorldGenConfig {
+    fn default() -> Self {
+        Self {
+            width: 200,
+            height: 100,
+            seed: 0,
+            octaves: vec![
+                Octave {
+                    weight: 0.25,
+                    wavelength: 400.0,
+                },
+                Octave {
+                    weight: 0.1,
+                    wavelength: 100.0,
+                },
+                Octave {
+                    weight: 0.015,
+                    wavelength: 21.0,
+                },
+            ],
+            min_height: 0.05,
+            max_height: 0.6,
+            layers: vec![
+                Layer {
+                    tile: WorldTile::Dirt,
+                    depth: LayerDepth::BelowSurface(Variation {
+                        min: 4.0,
+                        max: 10.0,
+                        wavelength: 40.0,
+                    }),
+                },
+                Layer {
+                    tile: WorldTile::Stone,
+                    depth: LayerDepth::Bottom,
+                },
+            ],
+            bedrock: Some(Variation {
+                min: 1.0,
+                max: 3.0,
+                wavelength: 15.0,
+            }),
+            ores: vec![OreConfig {
+                tile: WorldTile::Metal,
+                min_depth: 12.0,
+                density: 0.15,
+                size: 4.0,
+            }],
+            caves: Some(CaveConfig::default()),
+        }
+    }
+}
+
+/// Configures the carving of caves, tunnels and overhangs
+#[derive(Debug, Clone, Copy)]
+pub struct CaveConfig {
+    /// Roughly the fraction of the terrain that gets carved out, between 0 and 1
+    pub density: f32,
+    /// The typical size of a cave in tiles
+    pub size: f32,
+    /// The amount of rows at the bottom of the world that never get carved
+    pub floor_thickness: usize,
+}
+
+impl Default for CaveConfig {
+    fn default() -> Self {
+        Self {
+            density: 0.35,
+            size: 12.0,
+            floor_thickness: 4,
+        }
+    }
+}
+
+/// A sine wave that contributes to the surface height
+#[derive(Debug, Clone, Copy)]
+pub struct Octave {
+    /// How much this octave contributes relative to the other octaves
+    pub weight: f32,
+    /// The length of one oscillation in tiles
+    pub wavelength: f32,
+}
+
+/// A layer of one material below the surface
+#[derive(Debug, Clone, Copy)]
+pub struct Layer {
+    pub tile: WorldTile,
+    /// Where this layer ends
+    pub depth: LayerDepth,
+}
+
+/// Describes the depth at which a layer ends, relative to the surface height of each column
+#[derive(Debug, Clone, Copy)]
+pub enum LayerDepth {
+    /// The layer ends this many tiles below the surface
+    BelowSurface(Variation),
+    /// The layer ends at this fraction of the surface height, so it is thicker below hills
+    SurfaceFraction(f32),
+    /// The layer extends to the bottom of the world
+    Bottom,
+}
+
+/// A value that varies smoothly along the x axis
+#[derive(Debug, Clone, Copy)]
+pub struct Variation {
+    pub min: f32,
+    pub max: f32,
+    /// The typical distance in tiles between a minimum and a maximum
+    pub wavelength: f32,
+}
+
+/// Scatters pockets of a material below the surface
+#[derive(Debug, Clone, Copy)]
+pub struct OreConfig {
+    pub tile: WorldTile,
+    /// The minimum depth below the surface in tiles
+    pub min_depth: f32,
+    /// Roughly the fraction of the terrain that is converted to this material, between 0 and 1
+    pub density: f32,
+    /// The typical size of a pocket in tiles
+    pub size: f32,
+}
+
+impl Variation {
+    fn at_x(&self, x: f32, seed: u64) -> f32 {
+        let noise = value_noise_2d(seed, Vec2::new(x / self.wavelength, 0.0));
+        self.min + noise * (self.max - self.min)
+    }
+}
+
+impl LayerDepth {
+    /// Returns the depth below the surface at which the layer ends
+    fn at_x(&self, x: f32, surface_height: f32, seed: u64) -> f32 {
+        match self {
+            LayerDepth::BelowSurface(variation) => variation.at_x(x, seed),
+            LayerDepth::SurfaceFraction(fraction) => surface_height * (1.0 - fraction),
+            LayerDepth::Bottom => f32::INFINITY,
+        }
+    }
+}
+
+impl OreConfig {
+    fn is_ore_at(&self, pos: Vec2, seed: u64) -> bool {
+        value_noise_2d(seed, pos / self.size) > 1.0 - self.density
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Wave {
+    pub height: f32,
+    pub speed: f32,
+    pub off_y: f32,
+    pub off_x: f32,
+}
+
+impl Wave {
+    fn at_x(&self, x: f32) -> f32 {
+        self.off_y + ((x + self.off_x) * self.speed).sin() * self.height
+    }
+}
+
+impl Octave {
+    /// Creates a wave from this octave with a random phase
+    fn to_wave(self, rng: &mut Rng) -> Wave {
+        Wave {
+            height: self.weight,
+            speed: TAU / self.wavelength,
+            off_y: 0.0,
+            off_x: rng.range_f32(0.0, self.wavelength),
+        }
+    }
+}
+
+pub(super) fn generate_world(config: &WorldGenConfig) -> World {
+    let mut rng = Rng::new(config.seed);
+    let waves: Vec<_> = config
+        .octaves
+        .iter()
+        .map(|octave| octave.to_wave(&mut rng))
+        .collect();
+    let total_weight: f32 = waves.iter().map(|wave| wave.height).sum();
+    let layer_seeds: Vec<_> = config.layers.iter().map(|_| rng.next_u64()).collect();
+    let ore_seeds: Vec<_> = config.ores.iter().map(|_| rng.next_u64()).collect();
+    let bedrock_seed = rng.next_u64();
+
+    let mut world = World::new(config.width, config.height);
+    let min_height = config.min_height * world.height as f32;
+    let max_height = config.max_height * world.height as f32;
+    for x in 0..world.width {
+        let wave_height: f32 = waves.iter().map(|wave| wave.at_x(x as f32)).sum();
+        // Map the wave height from [-total_weight, total_weight] to [min_height, max_height]
+        let normalized_height = if total_weight > 0.0 {
+            (wave_height / total_weight + 1.0) / 2.0
+        } else {
+            0.5
+        };
+        let height = min_height + normalized_height * (max_height - min_height);
+        let layer_depths: Vec<_> = config
+            .layers
+            .iter()
+            .zip(&layer_seeds)
+            .map(|(layer, seed)| layer.depth.at_x(x as f32, height, *seed))
+            .collect();
+        let bedrock_height = config
+            .bedrock
+            .map_or(0.0, |bedrock| bedrock.at_x(x as f32, bedrock_seed));
+
+        let column_height = usize::min(world.height, height.max(bedrock_height) as usize);
+        for y in 0..column_height {
+            let depth = height - y as f32;
+            let pos = Vec2::new(x as f32, y as f32);
+            let tile = if (y as f32) < bedrock_height {
+                WorldTile::Bedrock
+            } else if let Some(ore) = config
+                .ores
+                .iter()
+                .zip(&ore_seeds)
+                .find(|(ore, seed)| depth >= ore.min_depth && ore.is_ore_at(pos, **seed))
+            {
+                ore.0.tile
+            } else {
+                layer_at_depth(&config.layers, &layer_depths, depth)
+            };
+            world.set(x as isize, y as isize, tile);
+        }
+    }
+    if let Some(caves) = &config.caves {
+        carve_caves(&mut world, caves, rng.next_u64());
+    }
+    // The generated terrain should stay as it is until it gets modified
+    world.settle();
+
+    world
+}
+
+/// Returns the material of the first layer that ends below the given depth
+fn layer_at_depth(layers: &[Layer], layer_depths: &[f32], depth: f32) -> WorldTile {
+    layers
+        .iter()
+        .zip(layer_depths)
+        .find(|(_, layer_depth)| depth <= **layer_depth)
+        .or(layers.last().zip(layer_depths.last()))
+        .map_or(WorldTile::Dirt, |(layer, _)| layer.tile)
+}
+
+/// Removes all tiles where the noise exceeds a threshold that depends on the cave density
+fn carve_caves(world: &mut World, config: &CaveConfig, seed: u64) {
+    let threshold = 1.0 - config.density;
+    for y in config.floor_thickness..world.height {
+        for x in 0..world.width {
+            let pos = Vec2::new(x as f32, y as f32) / config.size;
+            // Adding a finer octave makes the cave walls less regular
+            let noise = value_noise_2d(seed, pos) * 0.75 + value_noise_2d(!seed, pos * 3.0) * 0.25;
+            if noise > threshold && world.get(x as isize, y as isize).is_destructible() {
+                world.set(x as isize, y as isize, WorldTile::Air);
+            }
+        }
+    }
+
+    // Carving may cut off parts of the terrain, which would collapse as soon as the world gets modified
+    for island in world.find_floating_islands() {
+        for (x, y) in island.tiles {
+            world.set(x, y, WorldTile::Air);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::default;
+
+    use super::*;
+
+    fn tiles(world: &World) -> Vec<WorldTile> {
+        (0..world.height as isize)
+            .flat_map(|y| (0..world.width as isize).map(move |x| world.get(x, y)))
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_generates_same_world() {
+        let config = WorldGenConfig {
+            seed: 42,
+            ..default()
+        };
+        let world = World::generate(&config);
+        assert_eq!(tiles(&world), tiles(&World::generate(&config)));
+
+        // The default config uses layers, bedrock, ores and caves, which all have to be deterministic
+        let generated = tiles(&world);
+        for tile in [
+            WorldTile::Dirt,
+            WorldTile::Stone,
+            WorldTile::Bedrock,
+            WorldTile::Metal,
+        ] {
+            assert!(generated.contains(&tile), "{tile:?} was not generated");
+        }
+        let has_caves = (0..world.width as isize)
+            .any(|x| (0..world.surface_height(x)).any(|y| world.get(x, y) == WorldTile::Air));
+        assert!(has_caves);
+    }
+
+    #[test]
+    fn different_seeds_generate_different_worlds() {
+        let world = World::generate(&WorldGenConfig {
+            seed: 1,
+            ..default()
+        });
+        let other_world = World::generate(&WorldGenConfig {
+            seed: 2,
+            ..default()
+        });
+        assert_ne!(tiles(&world), tiles(&other_world));
+    }
+}
@@ -24,6 +24,8 @@ use bevy::{
     utils::FloatOrd,
 };
 
+pub mod mesher;
+
 /// A marker component for world meshes
 #[derive(Component, Default)]
 pub struct WorldMesh2d;
@@ -70,6 +72,8 @@ impl SpecializedRenderPipeline for WorldMesh2dPipeline {
 
         // I have no idea what this means
         let mut push_constant_ranges = Vec::with_capacity(1);
+        // These are features of bevy, which this crate doesn't declare
+        #[allow(unexpected_cfgs)]
         if cfg!(all(
             feature = "webgl2",
             target_arch = "wasm32",
@@ -177,6 +181,7 @@ impl Plugin for WorldMeshPlugin {
     }
 }
 
+#[allow(clippy::type_complexity)]
 pub fn extract_world_mesh2d(
     mut commands: Commands,
     mut previous_len: Local<usize>,
@@ -0,0 +1,285 @@
+use bevy::{
+    prelude::*,
+    render::{
+        mesh::{Indices, MeshVertexAttribute},
+        render_asset::RenderAssetUsages,
+        render_resource::{PrimitiveTopology, VertexFormat},
+    },
+};
+
+use crate::{
+    world::{
+        tile_shape::{connects_to, TileShape},
+        World,
+    },
+    TILE_SIZE,
+};
+
+/// The neighbors bitset of a tile that is surrounded by other tiles
+const FULL_NEIGHBORS: u32 = 0b11111;
+
+/// Builds a mesh from one chunk of the world.
+///
+/// Air tiles are skipped, and rectangles of tiles with the same color
+/// that are rendered as full squares are merged into a single quad.
+pub fn gen_chunk_mesh(world: &World, chunk_pos: (usize, usize)) -> Mesh {
+    let (x_range, y_range) = world.chunk_bounds(chunk_pos);
+    let mut builder = MeshBuilder::default();
+    // Whether the tile at the index has already been added to the mesh
+    let mut meshed = vec![false; x_range.len() * y_range.len()];
+    let local_index =
+        |x: usize, y: usize| (x - x_range.start) + (y - y_range.start) * x_range.len();
+
+    for y in y_range.clone() {
+        for x in x_range.clone() {
+            if meshed[local_index(x, y)] {
+                continue;
+            }
+
+            let neighbors = neighbors_bitset(world, x as isize, y as isize);
+            if neighbors & 0b10000 == 0 {
+                continue;
+            }
+
+            let color = world.get(x as isize, y as isize).color();
+            if !is_fully_covered(neighbors) {
+                meshed[local_index(x, y)] = true;
+                builder.add_quad(x, y, 1, 1, color, neighbors);
+                continue;
+            }
+
+            // Greedily extend the quad to the right and then upwards
+            let can_merge = |x: usize, y: usize, meshed: &[bool]| {
+                !meshed[local_index(x, y)]
+                    && is_fully_covered(neighbors_bitset(world, x as isize, y as isize))
+                    && world.get(x as isize, y as isize).color() == color
+            };
+            let width = (x..x_range.end)
+                .take_while(|&x| can_merge(x, y, &meshed))
+                .count();
+            let height = (y..y_range.end)
+                .take_while(|&y| (x..(x + width)).all(|x| can_merge(x, y, &meshed)))
+                .count();
+            for merged_y in y..(y + height) {
+                for merged_x in x..(x + width) {
+                    meshed[local_index(merged_x, merged_y)] = true;
+                }
+            }
+            builder.add_quad(x, y, width, height, color, FULL_NEIGHBORS);
+        }
+    }
+
+    builder.build()
+}
+
+/// Calculates the bitset of solid neighbors that the shader uses to smooth the edges of a tile.
+/// The bits are in the order top - left - bottom - right - self, where self is set for all tiles
+/// that are not air. Neighbors are solid by the same rules that the collisions use, see [`connects_to`].
+pub fn neighbors_bitset(world: &World, x: isize, y: isize) -> u32 {
+    let tile = world.get(x, y);
+    let [top, left, bottom, right] = world
+        .get_rendered(x, y)
+        .neighbors
+        .map(|neighbor| connects_to(tile, neighbor) as u32);
+    let self_on = tile.is_not_air() as u32;
+    top | left << 1 | bottom << 2 | right << 3 | self_on << 4
+}
+
+/// Returns whether the shader renders a tile with these neighbors as a full square
+fn is_fully_covered(neighbors: u32) -> bool {
+    TileShape::from_neighbors_bitset(neighbors).is_full()
+}
+
+#[derive(Debug, Default)]
+struct MeshBuilder {
+    positions: Vec<[f32; 3]>,
+    local_positions: Vec<[f32; 2]>,
+    colors: Vec<u32>,
+    neighbors: Vec<u32>,
+    indices: Vec<u32>,
+}
+
+impl MeshBuilder {
+    /// Adds a quad covering `width` x `height` tiles with the bottom left tile at (x|y)
+    fn add_quad(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        color: Color,
+        neighbors: u32,
+    ) {
+        let cx = x as f32 * TILE_SIZE;
+        let cy = y as f32 * TILE_SIZE;
+        let nx = (x + width) as f32 * TILE_SIZE;
+        let ny = (y + height) as f32 * TILE_SIZE;
+        let index = self.positions.len() as u32;
+        self.positions
+            .extend([[cx, cy, 0.0], [nx, cy, 0.0], [nx, ny, 0.0], [cx, ny, 0.0]]);
+        self.local_positions
+            .extend([[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]);
+        self.indices
+            .extend([index, index + 1, index + 2, index + 2, index + 3, index]);
+        self.colors.extend([color.as_linear_rgba_u32(); 4]);
+        self.neighbors.extend([neighbors; 4]);
+    }
+
+    fn build(self) -> Mesh {
+        let mut world_mesh = Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::RENDER_WORLD,
+        );
+        // Set the position attribute
+        world_mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.positions);
+        // And a RGB color attribute as well
+        world_mesh.insert_attribute(
+            MeshVertexAttribute::new("Vertex_Color", 1, VertexFormat::Uint32),
+            self.colors,
+        );
+        world_mesh.insert_attribute(
+            MeshVertexAttribute::new("Vertex_LocalPos", 2, VertexFormat::Float32x2),
+            self.local_positions,
+        );
+        world_mesh.insert_attribute(
+            MeshVertexAttribute::new("Vertex_Neighbors", 3, VertexFormat::Uint32),
+            self.neighbors,
+        );
+        world_mesh.insert_indices(Indices::U32(self.indices));
+
+        world_mesh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{render::mesh::VertexAttributeValues, utils::HashSet};
+
+    use super::*;
+    use crate::world::{chunk::CHUNK_SIZE, WorldTile};
+
+    /// Builds the mesh without merging, with one quad for every tile that isn't air
+    fn gen_naive_chunk_mesh(world: &World, chunk_pos: (usize, usize)) -> Mesh {
+        let (x_range, y_range) = world.chunk_bounds(chunk_pos);
+        let mut builder = MeshBuilder::default();
+        for y in y_range {
+            for x in x_range.clone() {
+                let neighbors = neighbors_bitset(world, x as isize, y as isize);
+                if neighbors & 0b10000 != 0 {
+                    let color = world.get(x as isize, y as isize).color();
+                    builder.add_quad(x, y, 1, 1, color, neighbors);
+                }
+            }
+        }
+        builder.build()
+    }
+
+    /// Returns every tile covered by a quad of the mesh and the total area of all quads in tiles
+    fn coverage(mesh: &Mesh) -> (HashSet<(usize, usize)>, usize) {
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("The mesh has no positions");
+        };
+        let mut tiles = HashSet::new();
+        let mut area = 0;
+        // Every quad has four vertices, starting at the bottom left and going counterclockwise
+        for quad in positions.chunks(4) {
+            let min = Vec2::from_slice(&quad[0]) / TILE_SIZE;
+            let max = Vec2::from_slice(&quad[2]) / TILE_SIZE;
+            let (min, max) = (min.as_uvec2(), max.as_uvec2());
+            for x in min.x..max.x {
+                for y in min.y..max.y {
+                    tiles.insert((x as usize, y as usize));
+                    area += 1;
+                }
+            }
+        }
+        (tiles, area)
+    }
+
+    /// A world of 3x3 chunks, so that the chunk in the middle has neighbors on every side
+    fn filled_world(tile_at: impl Fn(isize, isize) -> WorldTile) -> World {
+        World::filled(CHUNK_SIZE * 3, CHUNK_SIZE * 3, tile_at)
+    }
+
+    #[test]
+    fn uniform_chunk_is_a_single_quad() {
+        let world = filled_world(|_, _| WorldTile::Stone);
+        let mesh = gen_chunk_mesh(&world, (1, 1));
+        assert_eq!(mesh.count_vertices(), 4);
+        assert_eq!(coverage(&mesh).1, CHUNK_SIZE * CHUNK_SIZE);
+    }
+
+    #[test]
+    fn checkerboard_is_not_merged() {
+        let world = filled_world(|x, y| {
+            if (x + y) % 2 == 0 {
+                WorldTile::Dirt
+            } else {
+                WorldTile::Air
+            }
+        });
+        let mesh = gen_chunk_mesh(&world, (1, 1));
+        let naive_mesh = gen_naive_chunk_mesh(&world, (1, 1));
+        assert_eq!(mesh.count_vertices(), CHUNK_SIZE * CHUNK_SIZE / 2 * 4);
+        assert_eq!(mesh.count_vertices(), naive_mesh.count_vertices());
+    }
+
+    #[test]
+    fn tiles_are_drawn_with_their_collision_shape() {
+        // Dirt with water on top and to the right, which has no collider
+        let mut world = World::new(4, 4);
+        for (x, y, tile) in [
+            (1, 1, WorldTile::Dirt),
+            (0, 1, WorldTile::Dirt),
+            (1, 2, WorldTile::Water),
+            (2, 1, WorldTile::Water),
+        ] {
+            world.set(x, y, tile);
+        }
+        let drawn = TileShape::from_neighbors_bitset(neighbors_bitset(&world, 1, 1));
+        assert_eq!(drawn, world.get_rendered(1, 1).shape());
+        assert!(!drawn.is_full());
+        // The water still fills the space up to the dirt
+        let water = TileShape::from_neighbors_bitset(neighbors_bitset(&world, 2, 1));
+        assert_ne!(water, TileShape::Diamond);
+    }
+
+    #[test]
+    fn merged_mesh_covers_the_same_tiles() {
+        // Terrain with a wavy surface, holes and stripes of different materials
+        let world = filled_world(|x, y| {
+            let surface = 60 + (x % 17 - 8).abs();
+            if y >= surface || (x * 7 + y * 13) % 23 == 0 {
+                WorldTile::Air
+            } else if (y / 5) % 2 == 0 {
+                WorldTile::Dirt
+            } else {
+                WorldTile::Stone
+            }
+        });
+        for chunk_x in 0..3 {
+            for chunk_y in 0..3 {
+                let mesh = gen_chunk_mesh(&world, (chunk_x, chunk_y));
+                let naive_mesh = gen_naive_chunk_mesh(&world, (chunk_x, chunk_y));
+                let (tiles, area) = coverage(&mesh);
+                let (naive_tiles, naive_area) = coverage(&naive_mesh);
+                assert_eq!(tiles, naive_tiles);
+                // Quads must not overlap
+                assert_eq!(area, naive_area);
+                assert!(mesh.count_vertices() <= naive_mesh.count_vertices());
+            }
+        }
+        let (x_range, y_range) = world.chunk_bounds((1, 1));
+        let expected: HashSet<_> = x_range
+            .flat_map(|x| y_range.clone().map(move |y| (x, y)))
+            .filter(|&(x, y)| world.get(x as isize, y as isize).is_not_air())
+            .collect();
+        assert_eq!(coverage(&gen_chunk_mesh(&world, (1, 1))).0, expected);
+        assert!(
+            gen_chunk_mesh(&world, (1, 0)).count_vertices()
+                < gen_naive_chunk_mesh(&world, (1, 0)).count_vertices()
+        );
+    }
+}